@@ -0,0 +1,59 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// code-radio-cli is a binary crate, so the modules are included directly.
+// They only depend on each other, not on the terminal or the player.
+#[allow(dead_code)]
+#[path = "../../src/model.rs"]
+mod model;
+#[allow(dead_code)]
+#[path = "../../src/schema.rs"]
+mod schema;
+#[allow(dead_code)]
+#[path = "../../src/song_info.rs"]
+mod song_info;
+#[allow(dead_code)]
+#[path = "../../src/utils.rs"]
+mod utils;
+
+// The default layout's value column, and a narrow terminal
+const WRAP_WIDTHS: [usize; 2] = [68, 1];
+
+// Any message from the server should either parse or return an error, never panic,
+// and so should everything displayed from it
+fuzz_target!(|data: &[u8]| {
+    let Ok(message_text) = std::str::from_utf8(data) else {
+        return;
+    };
+    let _ = schema::validate(message_text);
+
+    let Ok(mut message) = serde_json::from_str::<model::CodeRadioMessage>(message_text) else {
+        return;
+    };
+    let _ = message.get_stations();
+    message.split_titles();
+
+    let station_name = song_info::get_station_name_from_api_message(&message);
+    let template = utils::Template::parse("{artist} - {title} [{elapsed}/{duration}] {station}");
+    let _ = template.render(&song_info::get_template_values(&message, &station_name));
+
+    let song = &message.now_playing.song;
+    let mut values = vec![song.title.as_str(), song.artist.as_str(), song.album.as_str()];
+    values.extend(song_info::get_extra_song_metadata(song).into_iter().map(|(_, value)| value));
+    if let Some(next_song) = song_info::get_next_song(&message) {
+        values.push(next_song.title.as_str());
+    }
+    for value in values {
+        for width in WRAP_WIDTHS {
+            let _ = utils::wrap_text(value, width);
+        }
+    }
+
+    let _ = song_info::get_progress_bar_suffix(
+        message.listeners.current,
+        message.listeners.unique,
+        Some(message.listeners.total),
+        Some(message.listeners.total),
+    );
+});
@@ -1,8 +1,44 @@
 use clap::Parser;
+use std::{path::PathBuf, time::Duration};
+
+use crate::{
+    art_cache,
+    equalizer::EqPreset,
+    local_time::TimeOfDay,
+    pcm_out::PcmFormat,
+    player::{Channels, VolumeCurve},
+    terminal::ProgressMode,
+    theme::ThemeName,
+    utils,
+};
 
 const ABOUT: &str = "A command line music radio client for https://coderadio.freecodecamp.org
 GitHub: https://github.com/JasonWei512/code-radio-cli";
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ListenersFormat {
+    Simple,   // "Listeners: 69"
+    Detailed, // "Listeners: 69 (52 unique)"
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TimestampFormat {
+    Time, // "07:30:00"
+    Iso,  // "2022-10-14T07:30:00+08:00"
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Bell {
+    Terminal, // The terminal bell character
+    Chime,    // A short tone mixed into the audio
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ErrorFormat {
+    Pretty, // "Error: ..."
+    Json,   // `{"error": "...", "kind": "network", "code": 2}`
+}
+
 #[derive(Parser, Debug)]
 #[clap(author, version, about = ABOUT)]
 pub struct Args {
@@ -10,11 +46,357 @@ pub struct Args {
     #[clap(short, long)]
     pub select_station: bool,
 
+    /// With `--select-station`, play the default station if none is selected in this many seconds.
+    /// Not supported on Windows.
+    #[clap(long, value_name = "SECONDS", requires = "select-station")]
+    pub select_timeout: Option<u64>,
+
     /// Volume, between 0 and 9
-    #[clap(short, long, default_value_t = 9)]
+    #[clap(short, long, default_value_t = 9, value_parser = clap::value_parser!(u8).range(0..=9))]
     pub volume: u8,
 
+    /// Automatically play the highest bitrate station with at least this bitrate (kbps)
+    #[clap(long, value_name = "KBPS", conflicts_with = "select-station")]
+    pub min_bitrate: Option<i64>,
+
+    /// Automatically play the highest bitrate station with at most this bitrate (kbps)
+    #[clap(long, value_name = "KBPS", conflicts_with = "select-station")]
+    pub max_bitrate: Option<i64>,
+
+    /// Play the station played last time. Falls back to the default station if it no longer exists.
+    #[clap(
+        long,
+        conflicts_with_all = &["select-station", "min-bitrate", "max-bitrate"]
+    )]
+    pub resume: bool,
+
+    /// Play a random station
+    #[clap(
+        long,
+        conflicts_with_all = &["select-station", "min-bitrate", "max-bitrate", "resume"]
+    )]
+    pub random: bool,
+
+    /// With `--random`, only pick from favorite stations
+    #[clap(long, requires = "random")]
+    pub favorites_only: bool,
+
+    /// Seed of `--random`, to pick the same station every time
+    #[clap(long, value_name = "N", requires = "random")]
+    pub seed: Option<u64>,
+
+    /// Only list and play stations whose names contain this, or match it as a glob like "*mp3*". Can be repeated.
+    #[clap(long, value_name = "PATTERN")]
+    pub include: Vec<String>,
+
+    /// Hide stations whose names contain this, or match it as a glob. Wins over `--include`. Can be repeated.
+    #[clap(long, value_name = "PATTERN")]
+    pub exclude: Vec<String>,
+
+    /// Play this MP3 stream URL directly, without song info from the API. Works with other internet radios.
+    /// Other formats like AAC are not supported.
+    #[clap(
+        long,
+        value_name = "URL",
+        conflicts_with_all = &["select-station", "min-bitrate", "max-bitrate", "resume", "random", "follow"]
+    )]
+    pub url: Option<String>,
+
+    /// Ignore the cached station list when selecting a station
+    #[clap(long)]
+    pub refresh_stations: bool,
+
+    /// Press 0 to toggle mute instead of setting volume to 0
+    #[clap(short, long)]
+    pub toggle_mute: bool,
+
+    /// Wait until this local time (like "07:30") before starting to play, like an alarm clock
+    #[clap(long, value_name = "HH:MM")]
+    pub start_at: Option<TimeOfDay>,
+
+    /// Start paused. Press space or send "resume" to start playing.
+    #[clap(long)]
+    pub start_paused: bool,
+
+    /// Gradually raise the volume to `--volume` over this many seconds after starting to play
+    #[clap(long, value_name = "SECONDS")]
+    pub fade_in: Option<u64>,
+
+    /// Print the currently playing song in one line and exit, without playing audio. Useful for status bars.
+    #[clap(long)]
+    pub oneshot: bool,
+
+    /// Template of `--oneshot` output. Placeholders are the same as `--format`.
+    #[clap(long, value_name = "TEMPLATE", default_value = "{artist} - {title}")]
+    pub oneshot_format: String,
+
+    /// Print the stream URL that would be played and exit, without playing audio. Like `mpv $(code-radio --print-url)`.
+    #[clap(long, conflicts_with = "url")]
+    pub print_url: bool,
+
+    /// Save all stations as an M3U playlist to the file and exit, for use in other media players
+    #[clap(long, value_name = "FILE")]
+    pub export_m3u: Option<PathBuf>,
+
+    /// Stop showing the "New version available" notice until an even newer version is released, then exit
+    #[clap(long)]
+    pub dismiss_update: bool,
+
+    /// Print the config with defaults filled in, like all keybindings, and the value of every flag as JSON,
+    /// then exit. Secrets are redacted.
+    #[clap(long)]
+    pub print_effective: bool,
+
+    /// Delete cached album art, stations and release info, then exit
+    #[clap(long)]
+    pub clear_cache: bool,
+
+    /// Max size of the album art cache in temp dir, used by `--web-port`. The least recently used art is removed first.
+    #[clap(long, value_name = "MB", default_value_t = art_cache::DEFAULT_ART_CACHE_SIZE_MB)]
+    pub art_cache_size: u64,
+
+    /// Audio host to play with, like "alsa" or "jack". See `--list-hosts`. Defaults to the system default.
+    #[clap(long, value_name = "HOST")]
+    pub audio_host: Option<String>,
+
+    /// Print the available audio hosts and exit
+    #[clap(long)]
+    pub list_hosts: bool,
+
+    /// Buffer this many seconds of audio before playing and keep them buffered, to survive network jitter.
+    /// Adds the same delay behind the live stream. If the buffer runs dry, playback waits until it's refilled.
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..=60))]
+    pub buffer_ahead_seconds: Option<u32>,
+
+    /// Show how full the `--buffer-ahead-seconds` buffer is in the progress bar, like "buf:85%".
+    /// Highlighted when it runs low.
+    #[clap(long, requires = "buffer-ahead-seconds")]
+    pub buffer_status: bool,
+
+    /// How many times to retry initializing the audio device on startup before playing without audio,
+    /// and when the audio device is lost
+    #[clap(long, value_name = "N", default_value_t = 3)]
+    pub audio_retries: u32,
+
+    /// Don't initialize the audio device or play audio, only show song info.
+    /// Like when another player plays the stream.
+    #[clap(long, conflicts_with_all = &["url", "audio-host", "pcm-out", "bench"])]
+    pub no_audio: bool,
+
+    /// How many times to try fetching stations from the REST API, like for `--select-station` and `--export-m3u`
+    #[clap(long, value_name = "N", default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    pub rest_attempts: u32,
+
+    /// Also write the decoded audio to this file or named pipe, for other programs like encoders or visualizers.
+    /// Audio is dropped if the reader is slower than playback.
+    #[clap(long, value_name = "FILE")]
+    pub pcm_out: Option<PathBuf>,
+
+    /// Format of `--pcm-out`: interleaved 16-bit little-endian samples, after a WAV header or with no header
+    #[clap(long, value_name = "FORMAT", value_enum, default_value_t = PcmFormat::Wav, requires = "pcm-out")]
+    pub pcm_format: PcmFormat,
+
+    /// How volume levels map to loudness. "log" makes each level sound like an even step.
+    #[clap(long, value_name = "CURVE", value_enum, default_value_t = VolumeCurve::Linear)]
+    pub volume_curve: VolumeCurve,
+
+    /// Output channels. "mono" mixes both channels into one, like for a single speaker. Defaults to the stream's own.
+    #[clap(long, value_name = "LAYOUT", value_enum)]
+    pub channels: Option<Channels>,
+
+    /// Equalizer preset. "custom" uses the gains in dB in the config file, like `"equalizer": { "bass": 3, "mid": 0, "treble": -2 }`.
+    #[clap(long, value_name = "PRESET", value_enum, default_value_t = EqPreset::Flat)]
+    pub eq: EqPreset,
+
+    /// When to show the animated progress bar. Otherwise a status line is printed every 30 seconds.
+    #[clap(long, value_name = "WHEN", value_enum, default_value_t = ProgressMode::Auto)]
+    pub progress: ProgressMode,
+
+    /// Write each raw websocket message as one line to the file, or to stderr if no file is given. For debugging.
+    #[clap(long, hide = true, value_name = "FILE")]
+    pub dump_messages: Option<Option<PathBuf>>,
+
+    /// Time the startup steps, print them as "key=milliseconds" lines and exit. For performance issue reports.
+    #[clap(long, hide = true)]
+    pub bench: bool,
+
+    /// Min. milliseconds between progress bar repaints. Raise it to reduce CPU usage on slow terminals or over SSH.
+    #[clap(long, value_name = "MS", default_value_t = 50)]
+    pub refresh_rate: u64,
+
+    /// Exit with an error if not playing within this many seconds after connecting starts
+    #[clap(long, value_name = "SECONDS")]
+    pub startup_timeout: Option<u64>,
+
+    /// After this many connect failures in a row, wait `--breaker-cooldown` seconds before each retry
+    #[clap(long, value_name = "N", default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    pub breaker_threshold: u32,
+
+    /// Seconds to wait before each retry once `--breaker-threshold` is reached
+    #[clap(long, value_name = "N", default_value_t = 30)]
+    pub breaker_cooldown: u64,
+
+    /// Quit after this long no matter what, like "90m", "2h" or "1h30m". For unattended sessions.
+    #[clap(long, value_name = "DURATION", value_parser = utils::parse_duration)]
+    pub max_runtime: Option<Duration>,
+
+    /// Quit after no audio has played for this long, like "10m", because it's paused, stalled or has no audio device
+    #[clap(long, value_name = "TIME", value_parser = utils::parse_duration)]
+    pub exit_when_idle: Option<Duration>,
+
+    /// Reconnect the metadata websocket this often, like "6h", for servers that stop sending updates after a long time. Audio is not interrupted.
+    #[clap(long, value_name = "TIME", value_parser = utils::parse_duration)]
+    pub reconnect_interval: Option<Duration>,
+
+    /// Never exit on network errors. Keep reconnecting with backoff instead. For always-on setups.
+    #[clap(long, conflicts_with = "startup-timeout")]
+    pub follow: bool,
+
+    /// Check whether the API is reachable and its messages can be parsed, then exit
+    #[clap(long)]
+    pub check: bool,
+
+    /// Fail on API messages with unknown, missing or null fields, or fields of the wrong type, naming each field's JSON path.
+    /// For testing AzuraCast instances, like with `--check`.
+    #[clap(long)]
+    pub strict: bool,
+
+    /// For stations sending titles like "Artist - Track" without the artist, split them at the first " - "
+    #[clap(long)]
+    pub split_title: bool,
+
+    /// Template of each song's info. Placeholders: {title} {artist} {album} {genre} {year} {station} {listeners} {elapsed} {duration}
+    #[clap(long, value_name = "TEMPLATE")]
+    pub format: Option<String>,
+
+    /// Color theme, "default" if not set. Colors of each role can be customized in the config file.
+    #[clap(long, value_enum)]
+    pub theme: Option<ThemeName>,
+
+    /// Pick the "light" or "default" theme by the terminal's background color. Ignored with `--theme`.
+    #[clap(long)]
+    pub theme_from_terminal: bool,
+
+    /// Draw the progress bar with ASCII characters, for terminals or fonts without good Unicode support
+    #[clap(long)]
+    pub ascii_bar: bool,
+
+    /// Show the song, progress, volume and listeners on one line, for small terminals.
+    /// Only with the animated progress bar.
+    #[clap(long)]
+    pub mono_progress: bool,
+
+    /// Do not use colors in output
+    #[clap(long)]
+    pub no_color: bool,
+
+    /// How to print the error that stops the program. "json" is for wrappers that parse it.
+    #[clap(long, value_name = "FORMAT", value_enum, default_value_t = ErrorFormat::Pretty)]
+    pub error_format: ErrorFormat,
+
     /// Do not display logo
     #[clap(short, long)]
     pub no_logo: bool,
+
+    /// "detailed" also shows the unique listener count, if the station reports it
+    #[clap(long, value_name = "FORMAT", value_enum, default_value_t = ListenersFormat::Simple)]
+    pub listeners: ListenersFormat,
+
+    /// Show the local time when each song starts, like "[07:30:00]". Use `--timestamps=iso` for ISO 8601.
+    #[clap(
+        long,
+        value_name = "FORMAT",
+        value_enum,
+        min_values = 0,
+        max_values = 1,
+        require_equals = true,
+        default_missing_value = "time"
+    )]
+    pub timestamps: Option<TimestampFormat>,
+
+    /// Ring the terminal bell when the song changes. Use `--bell=chime` to play a short tone instead.
+    #[clap(
+        long,
+        value_name = "KIND",
+        value_enum,
+        min_values = 0,
+        max_values = 1,
+        require_equals = true,
+        default_missing_value = "terminal"
+    )]
+    pub bell: Option<Bell>,
+
+    /// Print stations added to or removed from the station list while playing
+    #[clap(long)]
+    pub notify_station_changes: bool,
+
+    /// Show the next song under the current song, if the station provides it
+    #[clap(long)]
+    pub show_next: bool,
+
+    /// Show the peak listener count of this session
+    #[clap(long)]
+    pub show_peak_listeners: bool,
+
+    /// Periodically print uptime, reconnect count and bytes streamed. Press "i" to print them anytime.
+    #[clap(long)]
+    pub stats: bool,
+
+    /// When quitting, print how long each station and artist was listened to
+    #[clap(long)]
+    pub print_stats_on_exit: bool,
+
+    /// User-Agent of API requests. An empty string falls back to the default "code-radio-cli/<VERSION>".
+    #[clap(long, value_name = "STRING", env = "CODE_RADIO_USER_AGENT")]
+    pub user_agent: Option<String>,
+
+    /// Now playing API of another AzuraCast instance, like "https://radio.example.com/api/live/nowplaying/coderadio".
+    /// The websocket API is expected at the same path.
+    #[clap(long, value_name = "URL")]
+    pub api_url: Option<String>,
+
+    /// API key of a private AzuraCast instance, sent as the "X-API-Key" header of API requests
+    #[clap(
+        long,
+        value_name = "KEY",
+        env = "CODE_RADIO_API_KEY",
+        hide_env_values = true
+    )]
+    pub api_key: Option<String>,
+
+    /// Custom header sent with API requests, like "Authorization: Bearer <TOKEN>". Can be repeated.
+    #[clap(long, value_name = "HEADER")]
+    pub header: Vec<String>,
+
+    /// DANGEROUS: Accept any TLS certificate of the API, album art and audio streams, like self-signed ones.
+    /// Anyone on the network can then intercept the connection. Prefer `--ca-cert`.
+    #[clap(long)]
+    pub insecure: bool,
+
+    /// Also trust this CA certificate (PEM file) for the API, album art and audio streams, like the CA of a
+    /// self-hosted instance. Update checks and webhooks still use the default verification.
+    #[clap(long, value_name = "FILE")]
+    pub ca_cert: Option<PathBuf>,
+
+    /// Serve a web UI on this port to control playback from a browser, like on a phone.
+    /// Anyone on the network can use it. Listening stats are at /metrics in OpenMetrics format.
+    #[clap(long, value_name = "PORT")]
+    pub web_port: Option<u16>,
+
+    /// POST song changes as JSON to this URL
+    #[clap(long, value_name = "URL")]
+    pub webhook: Option<String>,
+
+    /// Write the current song to this file, like "Artist - Title", for stream overlays or status bars
+    #[clap(long, value_name = "FILE")]
+    pub now_playing_file: Option<PathBuf>,
+
+    /// Custom header sent with webhook requests, like "Authorization: Bearer <TOKEN>"
+    #[clap(long, value_name = "HEADER", requires = "webhook")]
+    pub webhook_header: Vec<String>,
+
+    /// Only send a song to `--webhook` after it has played for this many seconds, to skip songs flapped past
+    /// on reconnects. `--now-playing-file` is still updated right away.
+    #[clap(long, value_name = "N", default_value_t = 20)]
+    pub min_play_seconds: u64,
 }
@@ -0,0 +1,130 @@
+use anyhow::{Context, Result};
+use once_cell::sync::{Lazy, OnceCell};
+use std::{path::PathBuf, time::SystemTime};
+use tokio::sync::Mutex;
+
+const ART_CACHE_DIR_NAME: &str = "code-radio-cli-art";
+pub const DEFAULT_ART_CACHE_SIZE_MB: u64 = 50;
+
+static ART_CACHE_DIR_PATH: Lazy<PathBuf> = Lazy::new(|| {
+    let mut pathbuf = std::env::temp_dir();
+    pathbuf.push(ART_CACHE_DIR_NAME);
+    pathbuf
+});
+
+static MAX_SIZE_BYTES: OnceCell<u64> = OnceCell::new();
+
+static FILE_IO_MUTEX: Mutex<()> = Mutex::const_new(());
+
+/// If not called, the cache is capped at `DEFAULT_ART_CACHE_SIZE_MB`
+pub fn set_max_size_mb(size_mb: u64) {
+    let _ = MAX_SIZE_BYTES.set(size_mb * 1024 * 1024);
+}
+
+/// Get the album art at the url, from the cache in temp dir if it's there.
+/// Otherwise download it and cache it, evicting the least recently used art if the cache is too big.
+pub async fn get(http_client: &reqwest::Client, url: &str) -> Result<Vec<u8>> {
+    let path = ART_CACHE_DIR_PATH.join(file_name(url));
+
+    {
+        let _file_io_mutex_guard = FILE_IO_MUTEX.lock().await;
+        if let Ok(art) = tokio::fs::read(&path).await {
+            // The modified time is the last used time, for eviction
+            let _ = std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(SystemTime::now()));
+            return Ok(art);
+        }
+    }
+
+    let art = http_client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await
+        .with_context(|| format!("Cannot download album art \"{url}\""))?
+        .to_vec();
+
+    let _file_io_mutex_guard = FILE_IO_MUTEX.lock().await;
+    tokio::fs::create_dir_all(ART_CACHE_DIR_PATH.as_path()).await?;
+    tokio::fs::write(&path, &art).await?;
+    evict().await?;
+
+    Ok(art)
+}
+
+/// Returns the number of bytes freed
+pub async fn clear() -> Result<u64> {
+    let _file_io_mutex_guard = FILE_IO_MUTEX.lock().await;
+
+    let size = list_files().await?.iter().map(|(_, size, _)| size).sum();
+    match tokio::fs::remove_dir_all(ART_CACHE_DIR_PATH.as_path()).await {
+        Ok(()) => Ok(size),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e).context("Cannot clear album art cache"),
+    }
+}
+
+// Remove the least recently used files until the cache fits in the size cap
+async fn evict() -> Result<()> {
+    let max_size = *MAX_SIZE_BYTES.get_or_init(|| DEFAULT_ART_CACHE_SIZE_MB * 1024 * 1024);
+
+    let mut files = list_files().await?;
+    files.sort_by_key(|(_, _, modified)| *modified);
+
+    let mut total_size: u64 = files.iter().map(|(_, size, _)| size).sum();
+    for (path, size, _) in files {
+        if total_size <= max_size {
+            break;
+        }
+        tokio::fs::remove_file(&path).await?;
+        total_size -= size;
+    }
+
+    Ok(())
+}
+
+// Path, size and modified time of each cached file
+async fn list_files() -> Result<Vec<(PathBuf, u64, SystemTime)>> {
+    let mut files = Vec::new();
+    let mut entries = match tokio::fs::read_dir(ART_CACHE_DIR_PATH.as_path()).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(files),
+        Err(e) => return Err(e.into()),
+    };
+
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        if metadata.is_file() {
+            files.push((
+                entry.path(),
+                metadata.len(),
+                metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            ));
+        }
+    }
+
+    Ok(files)
+}
+
+// FNV-1a, which unlike `DefaultHasher` is stable across Rust versions, so the cache survives upgrades
+fn file_name(url: &str) -> String {
+    let hash = url.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("{hash:016x}")
+}
+
+/// Like "image/jpeg", guessed from the first bytes
+pub fn content_type(art: &[u8]) -> &'static str {
+    match art {
+        [0xFF, 0xD8, 0xFF, ..] => "image/jpeg",
+        [0x89, b'P', b'N', b'G', ..] => "image/png",
+        [b'G', b'I', b'F', b'8', ..] => "image/gif",
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::{bail, Context, Result};
+use futures_util::StreamExt;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+use crate::{http_client, model::CodeRadioMessage, player::Player, terminal::writeline};
+
+const FIRST_FRAME_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time the startup sequence, then print the results as "key=milliseconds" lines.
+/// Like `start_playing()`, the websocket is connected while the audio device is initialized.
+///
+/// Points measured from the start, except `audio_device_init_ms` and `websocket_connect_ms`
+/// which are the durations of these steps.
+pub async fn run(websocket_api_url: &'static str) -> Result<()> {
+    let start_time = Instant::now();
+
+    let websocket_connect_task = tokio::spawn(async move {
+        let websocket_connect_start_time = Instant::now();
+        let websocket_stream = http_client::connect_websocket(websocket_api_url).await;
+        (websocket_stream, websocket_connect_start_time.elapsed())
+    });
+
+    let audio_device_init_start_time = Instant::now();
+    let player = tokio::task::spawn_blocking(Player::try_new).await??;
+    let audio_device_init = audio_device_init_start_time.elapsed();
+
+    let (websocket_stream, websocket_connect) = websocket_connect_task.await?;
+    let mut websocket_stream = websocket_stream?;
+    let message = websocket_stream
+        .next()
+        .await
+        .context("Connection closed before receiving a message")??;
+    let message: CodeRadioMessage = serde_json::from_str(message.into_text()?.as_str())?;
+    let first_message = start_time.elapsed();
+
+    player.play(&message.station.listen_url);
+    let first_audio_sample = loop {
+        if let Some(first_frame_decoded_at) = player.first_frame_decoded_at() {
+            break first_frame_decoded_at - start_time;
+        }
+        if start_time.elapsed() > first_message + FIRST_FRAME_TIMEOUT {
+            bail!("No audio within {} seconds", FIRST_FRAME_TIMEOUT.as_secs());
+        }
+        sleep(Duration::from_millis(5)).await;
+    };
+
+    let results = [
+        ("audio_device_init_ms", audio_device_init),
+        ("websocket_connect_ms", websocket_connect),
+        ("first_message_ms", first_message),
+        ("first_audio_sample_ms", first_audio_sample),
+    ];
+    for (key, duration) in results {
+        writeline!("{}={}", key, duration.as_millis());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use anyhow::{Context, Result};
+use colored::Colorize;
+use futures_util::StreamExt;
+
+use crate::{
+    http_client,
+    terminal::writeline,
+    theme::{self, Role},
+};
+
+/// Check whether the REST API and the websocket API are reachable,
+/// and whether their messages can be parsed into `CodeRadioMessage`.
+/// The audio device is not touched, so that network problems can be told apart from audio problems.
+///
+/// Returns true if everything is OK.
+pub async fn check_connectivity(
+    http_client: &reqwest::Client,
+    rest_api_url: &str,
+    websocket_api_url: &str,
+) -> bool {
+    writeline!(
+        "{}   {}",
+        theme::paint("REST API:", Role::Accent),
+        rest_api_url
+    );
+    let rest_api_ok = print_check_result(fetch_rest_api_message(http_client, rest_api_url).await);
+
+    writeline!(
+        "{}  {}",
+        theme::paint("WebSocket:", Role::Accent),
+        websocket_api_url
+    );
+    let websocket_api_ok = print_check_result(fetch_websocket_api_message(websocket_api_url).await);
+
+    rest_api_ok && websocket_api_ok
+}
+
+// Ok(message text) if reachable. Err if unreachable.
+async fn fetch_rest_api_message(http_client: &reqwest::Client, url: &str) -> Result<String> {
+    let text = http_client
+        .get(url)
+        .headers(http_client::api_headers())
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    Ok(text)
+}
+
+// Ok(message text) if reachable. Err if unreachable.
+async fn fetch_websocket_api_message(url: &str) -> Result<String> {
+    let mut websocket_stream = http_client::connect_websocket(url).await?;
+    let message = websocket_stream
+        .next()
+        .await
+        .context("Connection closed before receiving a message")??;
+    let _ = websocket_stream.close(None).await;
+    Ok(message.into_text()?)
+}
+
+// Returns true if the message is fetched and parsed
+fn print_check_result(message_text: Result<String>) -> bool {
+    let message_text = match message_text {
+        Ok(message_text) => {
+            writeline!("  {} Reachable", "✓".bright_green());
+            message_text
+        }
+        Err(e) => {
+            writeline!("  {} Unreachable: {}", "✗".bright_red(), e);
+            return false;
+        }
+    };
+
+    match crate::parse_message(&message_text) {
+        Ok(message) => {
+            let song = message.now_playing.song;
+            writeline!(
+                "  {} Parsed, now playing: {} - {}",
+                "✓".bright_green(),
+                song.artist,
+                song.title
+            );
+            true
+        }
+        Err(e) => {
+            writeline!("  {} Cannot parse message: {}", "✗".bright_red(), e);
+            false
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+/// Stops hammering a server that is down. After `threshold` consecutive failures, the circuit opens,
+/// and every further attempt waits for `cooldown` first. The first success closes it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    consecutive_failures: u32,
+}
+
+impl CircuitBreaker {
+    pub const fn new(threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            consecutive_failures: 0,
+        }
+    }
+
+    /// How long to wait before the next attempt, if the circuit is open
+    pub fn cooldown(&self) -> Option<Duration> {
+        (self.consecutive_failures >= self.threshold).then_some(self.cooldown)
+    }
+
+    pub const fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    pub fn record<T, E>(&mut self, result: &Result<T, E>) {
+        match result {
+            Ok(_) => self.consecutive_failures = 0,
+            Err(_) => self.consecutive_failures = self.consecutive_failures.saturating_add(1),
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use anyhow::{anyhow, Context, Result};
+use std::str::FromStr;
+
+/// A line command, like "volume 3".
+///
+/// When stdin is not a terminal, commands are read from stdin line by line, so that the player can be driven by scripts:
+///
+/// ```sh
+/// echo "volume 3" | code-radio
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Volume(u8), // Between 0 and 9
+    Pause,
+    Resume,
+    Live, // Jump to the live position
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let mut words = line.split_whitespace();
+        let name = words.next().context("Empty command")?;
+        let argument = words.next();
+
+        let command = match (name.to_lowercase().as_str(), argument) {
+            ("volume", Some(volume)) => {
+                let volume: u8 = volume
+                    .parse()
+                    .ok()
+                    .filter(|v| *v <= 9)
+                    .context(anyhow!("Volume must be between 0 and 9, got \"{volume}\""))?;
+                Self::Volume(volume)
+            }
+            ("volume", None) => return Err(anyhow!("Usage: volume <0-9>")),
+            ("pause", None) => Self::Pause,
+            ("resume" | "play", None) => Self::Resume,
+            ("live", None) => Self::Live,
+            ("quit" | "exit", None) => Self::Quit,
+            _ => return Err(anyhow!("Unknown command \"{}\"", line.trim())),
+        };
+
+        if words.next().is_some() {
+            return Err(anyhow!("Too many arguments in command \"{}\"", line.trim()));
+        }
+
+        Ok(command)
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::Mutex};
+
+use crate::{
+    equalizer::EqConfig, keybindings::KeyBindings, preferred_stations::StationPreference,
+    theme::ThemeConfig, update_checker::LatestReleaseCache,
+};
+
+const CONFIG_DIR_NAME: &str = "code-radio-cli";
+const CONFIG_FILE_NAME: &str = "config.json";
+
+/// Loaded from the config file on startup. See `Config::load()`.
+pub static CONFIG: Lazy<Mutex<Config>> = Lazy::new(|| Mutex::new(Config::default()));
+
+/// Persisted as JSON in the config file:
+///
+/// - Windows: `%APPDATA%\code-radio-cli\config.json`
+/// - macOS: `~/Library/Application Support/code-radio-cli/config.json`
+/// - Others: `$XDG_CONFIG_HOME/code-radio-cli/config.json` or `~/.config/code-radio-cli/config.json`
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Stored by station id, so that they survive station list reordering
+    pub favorite_station_ids: Vec<i64>,
+
+    /// The station played last time, for `--resume`
+    pub last_station_id: Option<i64>,
+
+    /// Station ids or names to try in order, like `[3, "Code Radio (mirror)"]`.
+    /// Played on startup unless a station is selected otherwise. When the stream fails, the next reachable one is played,
+    /// and the more preferred ones are switched back to when they recover.
+    pub preferred_stations: Vec<StationPreference>,
+
+    /// The "New version available" notice is not shown for this version. Set by `--dismiss-update`.
+    pub dismissed_release_version: Option<String>,
+
+    /// The latest release found on GitHub and when it was checked, so that it's checked at most once per day
+    pub latest_release_cache: Option<LatestReleaseCache>,
+
+    /// Like `{ "song": "bright blue", "warning": "red" }`
+    pub theme: ThemeConfig,
+
+    /// Gains in dB for `--eq custom`, like `{ "bass": 3, "mid": 0, "treble": -2 }`
+    pub equalizer: EqConfig,
+
+    /// Like `{ "quit": "x", "mute": "m" }`
+    pub keybindings: KeyBindings,
+}
+
+impl Config {
+    /// Returns the default config if the config file doesn't exist
+    pub fn load() -> Result<Self> {
+        let Some(path) = get_config_file_path() else {
+            return Ok(Self::default());
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Invalid config file \"{}\"", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => {
+                Err(e).with_context(|| format!("Cannot read config file \"{}\"", path.display()))
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = get_config_file_path().context("Cannot find config directory")?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(&path, content)
+            .with_context(|| format!("Cannot write config file \"{}\"", path.display()))?;
+
+        Ok(())
+    }
+
+    /// Returns true if the last station changed, so that the config needs saving
+    pub fn set_last_station(&mut self, station_id: i64) -> bool {
+        self.last_station_id.replace(station_id) != Some(station_id)
+    }
+
+    pub fn is_favorite(&self, station_id: i64) -> bool {
+        self.favorite_station_ids.contains(&station_id)
+    }
+
+    /// Returns true if the station becomes a favorite, false if it's no longer a favorite
+    pub fn toggle_favorite(&mut self, station_id: i64) -> bool {
+        if self.is_favorite(station_id) {
+            self.favorite_station_ids.retain(|id| *id != station_id);
+            false
+        } else {
+            self.favorite_station_ids.push(station_id);
+            true
+        }
+    }
+}
+
+pub fn get_config_file_path() -> Option<PathBuf> {
+    let mut pathbuf = get_config_dir()?;
+    pathbuf.push(CONFIG_DIR_NAME);
+    pathbuf.push(CONFIG_FILE_NAME);
+    Some(pathbuf)
+}
+
+fn get_config_dir() -> Option<PathBuf> {
+    let non_empty_env_var = |key: &str| std::env::var_os(key).filter(|v| !v.is_empty());
+
+    if cfg!(windows) {
+        return non_empty_env_var("APPDATA").map(PathBuf::from);
+    }
+
+    let home = non_empty_env_var("HOME").map(PathBuf::from);
+
+    if cfg!(target_os = "macos") {
+        return home.map(|home| home.join("Library").join("Application Support"));
+    }
+
+    non_empty_env_var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home.map(|home| home.join(".config")))
+}
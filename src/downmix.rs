@@ -0,0 +1,81 @@
+use rodio::Source;
+use std::time::Duration;
+
+/// A source that averages the channels of each frame into one for `--channels mono`, or plays the inner source as is.
+///
+/// Averaging keeps the loudness of content that's the same in both channels, like most music's center,
+/// and never clips.
+pub struct Downmix<S>
+where
+    S: Source<Item = i16>,
+{
+    source: S,
+    enabled: bool,
+}
+
+impl<S> Downmix<S>
+where
+    S: Source<Item = i16>,
+{
+    pub const fn new(source: S, enabled: bool) -> Self {
+        Self { source, enabled }
+    }
+}
+
+impl<S> Iterator for Downmix<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    #[inline]
+    fn next(&mut self) -> Option<i16> {
+        let first_sample = self.source.next()?;
+        if !self.enabled {
+            return Some(first_sample);
+        }
+
+        // Read after the first sample, which may start a new frame with a different channel count
+        let channels = self.source.channels().max(1);
+        let mut sum = i32::from(first_sample);
+        for _ in 1..channels {
+            sum += i32::from(self.source.next()?);
+        }
+
+        Some((sum / i32::from(channels)) as i16)
+    }
+}
+
+impl<S> Source for Downmix<S>
+where
+    S: Source<Item = i16>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        let frame_len = self.source.current_frame_len()?;
+        if self.enabled {
+            Some(frame_len / usize::from(self.source.channels().max(1)))
+        } else {
+            Some(frame_len)
+        }
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        if self.enabled {
+            1
+        } else {
+            self.source.channels()
+        }
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
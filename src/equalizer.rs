@@ -0,0 +1,276 @@
+use anyhow::{anyhow, Result};
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::{f32::consts::PI, time::Duration};
+
+// The three bands: a low shelf, a peak in the middle, and a high shelf
+const BASS_FREQUENCY: f32 = 120.0;
+const MID_FREQUENCY: f32 = 2000.0;
+const MID_Q: f32 = 0.8;
+const TREBLE_FREQUENCY: f32 = 6000.0;
+
+const MAX_GAIN_DB: i32 = 12;
+
+/// Built-in equalizer settings, selected by `--eq`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum EqPreset {
+    Flat,
+    BassBoost,
+    Treble,
+    Vocal,
+    Custom, // The `equalizer` section in the config file
+}
+
+/// The `equalizer` section in the config file, used by `--eq custom`.
+/// Gains are in dB, between -12 and 12.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EqConfig {
+    pub bass: i32,
+    pub mid: i32,
+    pub treble: i32,
+}
+
+/// Gains of the bands in dB
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EqGains {
+    bass: f32,
+    mid: f32,
+    treble: f32,
+}
+
+impl EqGains {
+    pub fn from_preset(preset: EqPreset, config: &EqConfig) -> Result<Self> {
+        let (bass, mid, treble) = match preset {
+            EqPreset::Flat => (0, 0, 0),
+            EqPreset::BassBoost => (6, 0, 0),
+            EqPreset::Treble => (0, 0, 6),
+            EqPreset::Vocal => (-2, 4, 0),
+            EqPreset::Custom => {
+                for (band, gain) in [
+                    ("bass", config.bass),
+                    ("mid", config.mid),
+                    ("treble", config.treble),
+                ] {
+                    if gain.abs() > MAX_GAIN_DB {
+                        return Err(anyhow!(
+                            "Equalizer gain \"{band}\" in config must be between -{MAX_GAIN_DB} and {MAX_GAIN_DB} dB, got {gain}"
+                        ));
+                    }
+                }
+                (config.bass, config.mid, config.treble)
+            }
+        };
+
+        Ok(Self {
+            bass: bass as f32,
+            mid: mid as f32,
+            treble: treble as f32,
+        })
+    }
+
+    pub fn is_flat(&self) -> bool {
+        self.bass == 0.0 && self.mid == 0.0 && self.treble == 0.0
+    }
+
+    // Lower the whole signal by the highest boost, so that boosted bands don't clip
+    fn headroom(&self) -> f32 {
+        let max_gain = self.bass.max(self.mid).max(self.treble).max(0.0);
+        10_f32.powf(-max_gain / 20.0)
+    }
+}
+
+// Biquad coefficients from the Audio EQ Cookbook, normalized by a0
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct BiquadState {
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    fn low_shelf(frequency: f32, gain_db: f32, sample_rate: u32) -> Self {
+        let (a, cos, alpha) = Self::shelf_params(frequency, gain_db, sample_rate);
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+        Self::normalized(
+            a * ((a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha),
+            2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+            a * ((a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha),
+            (a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha,
+            -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+            (a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha,
+        )
+    }
+
+    fn high_shelf(frequency: f32, gain_db: f32, sample_rate: u32) -> Self {
+        let (a, cos, alpha) = Self::shelf_params(frequency, gain_db, sample_rate);
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+        Self::normalized(
+            a * ((a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+            a * ((a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha),
+            (a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos),
+            (a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha,
+        )
+    }
+
+    fn peaking(frequency: f32, q: f32, gain_db: f32, sample_rate: u32) -> Self {
+        let a = 10_f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * clamp_frequency(frequency, sample_rate) / sample_rate as f32;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        Self::normalized(
+            1.0 + alpha * a,
+            -2.0 * cos,
+            1.0 - alpha * a,
+            1.0 + alpha / a,
+            -2.0 * cos,
+            1.0 - alpha / a,
+        )
+    }
+
+    // With a shelf slope of 1
+    fn shelf_params(frequency: f32, gain_db: f32, sample_rate: u32) -> (f32, f32, f32) {
+        let a = 10_f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * clamp_frequency(frequency, sample_rate) / sample_rate as f32;
+        let alpha = w0.sin() / 2.0 * 2_f32.sqrt();
+        (a, w0.cos(), alpha)
+    }
+
+    fn normalized(b0: f32, b1: f32, b2: f32, a0: f32, a1: f32, a2: f32) -> Self {
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    // Transposed direct form II
+    #[inline]
+    fn process(&self, state: &mut BiquadState, x: f32) -> f32 {
+        let y = self.b0 * x + state.z1;
+        state.z1 = self.b1 * x - self.a1 * y + state.z2;
+        state.z2 = self.b2 * x - self.a2 * y;
+        y
+    }
+}
+
+// Keep the frequency below the Nyquist frequency for low sample rates
+fn clamp_frequency(frequency: f32, sample_rate: u32) -> f32 {
+    frequency.min(sample_rate as f32 * 0.45)
+}
+
+/// A source that applies the equalizer to the inner source, or plays it as is if the gains are `None` or flat.
+/// Coefficients are only recomputed when the sample rate changes.
+pub struct Equalizer<S>
+where
+    S: Source<Item = i16>,
+{
+    source: S,
+    gains: Option<EqGains>,
+    filters: Vec<Biquad>,
+    states: Vec<BiquadState>, // For each channel and filter
+    headroom: f32,
+    sample_rate: u32,
+    channels: u16,
+    channel: usize, // Of the next sample
+}
+
+impl<S> Equalizer<S>
+where
+    S: Source<Item = i16>,
+{
+    pub fn new(source: S, gains: Option<EqGains>) -> Self {
+        Self {
+            source,
+            gains: gains.filter(|gains| !gains.is_flat()),
+            filters: Vec::new(),
+            states: Vec::new(),
+            headroom: 1.0,
+            sample_rate: 0,
+            channels: 0,
+            channel: 0,
+        }
+    }
+
+    // When a new frame changes the format. Frames always start with the first channel.
+    fn reset(&mut self, gains: EqGains, sample_rate: u32, channels: u16) {
+        self.filters = vec![
+            Biquad::low_shelf(BASS_FREQUENCY, gains.bass, sample_rate),
+            Biquad::peaking(MID_FREQUENCY, MID_Q, gains.mid, sample_rate),
+            Biquad::high_shelf(TREBLE_FREQUENCY, gains.treble, sample_rate),
+        ];
+        self.states = vec![BiquadState::default(); self.filters.len() * usize::from(channels)];
+        self.headroom = gains.headroom();
+        self.sample_rate = sample_rate;
+        self.channels = channels;
+        self.channel = 0;
+    }
+}
+
+impl<S> Iterator for Equalizer<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    #[inline]
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.source.next()?;
+        let Some(gains) = self.gains else {
+            return Some(sample);
+        };
+
+        // Read after the sample, which may start a new frame
+        let sample_rate = self.source.sample_rate();
+        let channels = self.source.channels().max(1);
+        if sample_rate != self.sample_rate || channels != self.channels {
+            self.reset(gains, sample_rate, channels);
+        }
+
+        let states = &mut self.states[self.channel * self.filters.len()..][..self.filters.len()];
+        let mut y = f32::from(sample) * self.headroom;
+        for (filter, state) in self.filters.iter().zip(states) {
+            y = filter.process(state, y);
+        }
+        self.channel = (self.channel + 1) % usize::from(channels);
+
+        Some(y.clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16)
+    }
+}
+
+impl<S> Source for Equalizer<S>
+where
+    S: Source<Item = i16>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
@@ -0,0 +1,168 @@
+use std::fmt::{self, Display};
+
+/// The process exit code, so that scripts and supervisors can tell why the program stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitCode {
+    Success = 0,
+    Failure = 1, // Errors not covered below
+    NetworkFailure = 2,
+    AudioDeviceFailure = 3,
+    BadArguments = 4,
+}
+
+impl ExitCode {
+    /// Use the code attached with `ResultExt::exit_code()` if any,
+    /// otherwise guess it from the underlying error types
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        if let Some(e) = error
+            .chain()
+            .find_map(|e| e.downcast_ref::<ErrorWithExitCode>())
+        {
+            return e.exit_code;
+        }
+
+        for cause in error.chain() {
+            if cause.is::<reqwest::Error>()
+                || cause.is::<tokio_tungstenite::tungstenite::Error>()
+                || cause.is::<tokio::time::error::Elapsed>()
+            {
+                return Self::NetworkFailure;
+            }
+            if cause.is::<rodio::StreamError>() || cause.is::<rodio::PlayError>() {
+                return Self::AudioDeviceFailure;
+            }
+        }
+
+        Self::Failure
+    }
+
+    /// Like "network", for machine-readable error output
+    pub const fn kind(self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failure => "other",
+            Self::NetworkFailure => "network",
+            Self::AudioDeviceFailure => "audio_device",
+            Self::BadArguments => "bad_arguments",
+        }
+    }
+
+    pub fn exit(self) -> ! {
+        std::process::exit(self as i32)
+    }
+}
+
+pub trait ResultExt<T> {
+    /// Attach the exit code to use if this error stops the program
+    fn exit_code(self, exit_code: ExitCode) -> anyhow::Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> ResultExt<T> for Result<T, E> {
+    fn exit_code(self, exit_code: ExitCode) -> anyhow::Result<T> {
+        self.map_err(|e| {
+            ErrorWithExitCode {
+                exit_code,
+                error: e.into(),
+            }
+            .into()
+        })
+    }
+}
+
+// Displayed exactly like the wrapped error
+#[derive(Debug)]
+struct ErrorWithExitCode {
+    exit_code: ExitCode,
+    error: anyhow::Error,
+}
+
+impl Display for ErrorWithExitCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for ErrorWithExitCode {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.chain().nth(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+    use std::time::Duration;
+
+    #[test]
+    fn network_errors() {
+        let reqwest_error = reqwest::Client::new().get("not a url").build().unwrap_err();
+        assert_eq!(
+            ExitCode::from_error(&reqwest_error.into()),
+            ExitCode::NetworkFailure
+        );
+
+        let websocket_error = tokio_tungstenite::tungstenite::Error::ConnectionClosed;
+        assert_eq!(
+            ExitCode::from_error(&websocket_error.into()),
+            ExitCode::NetworkFailure
+        );
+    }
+
+    #[tokio::test]
+    async fn timeouts_are_network_errors() {
+        let elapsed = tokio::time::timeout(Duration::ZERO, std::future::pending::<()>())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            ExitCode::from_error(&elapsed.into()),
+            ExitCode::NetworkFailure
+        );
+    }
+
+    #[test]
+    fn audio_device_errors() {
+        assert_eq!(
+            ExitCode::from_error(&rodio::StreamError::NoDevice.into()),
+            ExitCode::AudioDeviceFailure
+        );
+        assert_eq!(
+            ExitCode::from_error(&rodio::PlayError::NoDevice.into()),
+            ExitCode::AudioDeviceFailure
+        );
+    }
+
+    #[test]
+    fn other_errors() {
+        assert_eq!(
+            ExitCode::from_error(&anyhow!("Something went wrong")),
+            ExitCode::Failure
+        );
+    }
+
+    #[test]
+    fn causes_are_looked_through() {
+        let error = Err::<(), _>(rodio::StreamError::NoDevice)
+            .context("Audio device initialization failed")
+            .unwrap_err();
+        assert_eq!(ExitCode::from_error(&error), ExitCode::AudioDeviceFailure);
+    }
+
+    #[test]
+    fn attached_exit_code_wins() {
+        let error = Err::<(), _>(tokio_tungstenite::tungstenite::Error::ConnectionClosed)
+            .exit_code(ExitCode::BadArguments)
+            .context("Cannot connect")
+            .unwrap_err();
+        assert_eq!(ExitCode::from_error(&error), ExitCode::BadArguments);
+        assert_eq!(error.root_cause().to_string(), "Connection closed normally");
+    }
+
+    #[test]
+    fn kinds() {
+        assert_eq!(ExitCode::NetworkFailure.kind(), "network");
+        assert_eq!(ExitCode::AudioDeviceFailure.kind(), "audio_device");
+        assert_eq!(ExitCode::BadArguments.kind(), "bad_arguments");
+        assert_eq!(ExitCode::Failure.kind(), "other");
+    }
+}
@@ -0,0 +1,234 @@
+use anyhow::{anyhow, Context, Result};
+use once_cell::sync::OnceCell;
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
+use std::{path::Path, time::Duration};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    tungstenite::client::IntoClientRequest, Connector, MaybeTlsStream, WebSocketStream,
+};
+
+pub const DEFAULT_USER_AGENT: &str = concat!("code-radio-cli/", env!("CARGO_PKG_VERSION"));
+
+const DEFAULT_REST_API_URL: &str =
+    "https://coderadio-admin.freecodecamp.org/api/live/nowplaying/coderadio";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Header of AzuraCast API keys
+const API_KEY_HEADER: &str = "X-API-Key";
+
+static USER_AGENT: OnceCell<String> = OnceCell::new();
+static API_URLS: OnceCell<ApiUrls> = OnceCell::new();
+static API_HEADERS: OnceCell<HeaderMap> = OnceCell::new();
+static TLS_OPTIONS: OnceCell<TlsOptions> = OnceCell::new();
+
+// The websocket API is at the same path as the REST API
+#[derive(Debug)]
+struct ApiUrls {
+    rest: String,
+    websocket: String,
+}
+
+#[derive(Debug, Default)]
+struct TlsOptions {
+    insecure: bool,               // Accept any certificate. Dangerous.
+    ca_cert_pem: Option<Vec<u8>>, // An extra trusted CA certificate
+}
+
+/// Set the User-Agent of REST and websocket requests. Call this before building the HTTP client.
+///
+/// An empty string falls back to `DEFAULT_USER_AGENT`.
+pub fn set_user_agent(user_agent: Option<String>) {
+    if let Some(user_agent) = user_agent.filter(|ua| !ua.trim().is_empty()) {
+        let _ = USER_AGENT.set(user_agent);
+    }
+}
+
+pub fn user_agent() -> &'static str {
+    USER_AGENT.get().map_or(DEFAULT_USER_AGENT, String::as_str)
+}
+
+/// Set the REST API URL, like "https://radio.example.com/api/live/nowplaying/coderadio" of a self-hosted
+/// AzuraCast instance. The websocket API URL is the same with the "ws" or "wss" scheme.
+/// Call this before connecting to the API.
+pub fn set_api_url(url: &str) -> Result<()> {
+    let mut websocket_url =
+        reqwest::Url::parse(url).with_context(|| format!("Invalid API URL \"{url}\""))?;
+    let websocket_scheme = match websocket_url.scheme() {
+        "https" => "wss",
+        "http" => "ws",
+        _ => return Err(anyhow!("API URL \"{url}\" is not an HTTP or HTTPS URL")),
+    };
+    websocket_url
+        .set_scheme(websocket_scheme)
+        .map_err(|_| anyhow!("Invalid API URL \"{url}\""))?;
+
+    let _ = API_URLS.set(ApiUrls {
+        rest: url.to_owned(),
+        websocket: websocket_url.into(),
+    });
+    Ok(())
+}
+
+pub fn rest_api_url() -> &'static str {
+    API_URLS.get_or_init(default_api_urls).rest.as_str()
+}
+
+pub fn websocket_api_url() -> &'static str {
+    API_URLS.get_or_init(default_api_urls).websocket.as_str()
+}
+
+fn default_api_urls() -> ApiUrls {
+    ApiUrls {
+        rest: DEFAULT_REST_API_URL.to_owned(),
+        websocket: DEFAULT_REST_API_URL.replacen("https", "wss", 1),
+    }
+}
+
+/// Set the headers sent with REST and websocket API requests, like the API key of a private AzuraCast instance.
+/// They're not sent with other requests like update checking or webhooks.
+///
+/// `headers` are like `["Authorization: Bearer <TOKEN>"]`.
+/// Values are marked as sensitive, so that they're not shown in debug output.
+pub fn set_api_headers(api_key: Option<&str>, headers: &[String]) -> Result<()> {
+    let mut header_map = HeaderMap::new();
+
+    for header in headers {
+        let (name, value) = parse_header(header)?;
+        let mut value = HeaderValue::from_str(&value)
+            .map_err(|_| anyhow!("Invalid value of header \"{name}\""))?;
+        value.set_sensitive(true);
+        header_map.append(
+            HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| anyhow!("Invalid header name \"{name}\""))?,
+            value,
+        );
+    }
+
+    if let Some(api_key) = api_key {
+        let mut value = HeaderValue::from_str(api_key).context("Invalid API key")?;
+        value.set_sensitive(true);
+        header_map.insert(API_KEY_HEADER, value);
+    }
+
+    let _ = API_HEADERS.set(header_map);
+    Ok(())
+}
+
+pub fn api_headers() -> HeaderMap {
+    API_HEADERS.get().cloned().unwrap_or_default()
+}
+
+/// Set how TLS certificates of the API, album art and audio streams are verified, like for self-hosted instances
+/// with self-signed certificates. Call this before building the HTTP client.
+///
+/// Update checks and webhooks use the external HTTP client, with the default verification.
+///
+/// `insecure` disables certificate verification. `ca_cert_path` is a PEM file of an extra trusted CA.
+pub fn set_tls_options(insecure: bool, ca_cert_path: Option<&Path>) -> Result<()> {
+    let ca_cert_pem = match ca_cert_path {
+        Some(path) => {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Cannot read CA certificate \"{}\"", path.display()))?;
+            // Fail early instead of on the first request
+            reqwest::Certificate::from_pem(&pem)
+                .with_context(|| format!("Invalid CA certificate \"{}\"", path.display()))?;
+            Some(pem)
+        }
+        None => None,
+    };
+
+    let _ = TLS_OPTIONS.set(TlsOptions {
+        insecure,
+        ca_cert_pem,
+    });
+    Ok(())
+}
+
+// "Authorization: Bearer <TOKEN>" -> ("Authorization", "Bearer <TOKEN>")
+pub fn parse_header(header: &str) -> Result<(String, String)> {
+    let (name, value) = header.split_once(':').context(anyhow!(
+        "Invalid header \"{header}\", expected \"NAME: VALUE\""
+    ))?;
+
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(anyhow!("Invalid header \"{header}\", header name is empty"));
+    }
+
+    Ok((name.to_owned(), value.trim().to_owned()))
+}
+
+/// Build the `reqwest::Client` shared by all REST requests to the API and album art, so that they share one
+/// connection pool and settings.
+///
+/// Proxy is read from `HTTP_PROXY`, `HTTPS_PROXY` and `ALL_PROXY` environment variables.
+/// Timeouts make sure a hung server doesn't freeze startup.
+///
+/// Don't use it for the audio stream, which is endless and would be cut off by the request timeout.
+pub fn build_http_client() -> Result<reqwest::Client> {
+    let tls_options = TLS_OPTIONS.get_or_init(TlsOptions::default);
+    let mut builder = rest_client_builder().danger_accept_invalid_certs(tls_options.insecure);
+    if let Some(ref pem) = tls_options.ca_cert_pem {
+        builder = builder.add_root_certificate(reqwest::Certificate::from_pem(pem)?);
+    }
+    builder.build().context("Cannot create HTTP client")
+}
+
+/// Build the `reqwest::Client` of requests to other servers than the API, like update checks and webhooks.
+/// The TLS options of the API don't apply, so `--insecure` can't weaken them.
+pub fn build_external_http_client() -> Result<reqwest::Client> {
+    rest_client_builder()
+        .build()
+        .context("Cannot create HTTP client")
+}
+
+fn rest_client_builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder()
+        .user_agent(user_agent())
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+}
+
+/// Build the blocking client of the audio stream, with the same TLS options as the REST client and no timeout
+pub fn build_stream_client() -> Result<reqwest::blocking::Client> {
+    let tls_options = TLS_OPTIONS.get_or_init(TlsOptions::default);
+    let mut builder = reqwest::blocking::Client::builder()
+        .timeout(None)
+        .danger_accept_invalid_certs(tls_options.insecure);
+    if let Some(ref pem) = tls_options.ca_cert_pem {
+        builder = builder.add_root_certificate(reqwest::Certificate::from_pem(pem)?);
+    }
+    builder.build().context("Cannot create HTTP client")
+}
+
+/// Like `tokio_tungstenite::connect_async()`, with the User-Agent header and API headers set
+pub async fn connect_websocket(url: &str) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+    let mut request = url.into_client_request()?;
+    request.headers_mut().insert(
+        header::USER_AGENT,
+        HeaderValue::from_str(user_agent()).context("Invalid User-Agent")?,
+    );
+    request.headers_mut().extend(api_headers());
+
+    let (websocket_stream, _) =
+        tokio_tungstenite::connect_async_tls_with_config(request, None, build_tls_connector()?)
+            .await?;
+    Ok(websocket_stream)
+}
+
+// `None` uses the default TLS settings
+fn build_tls_connector() -> Result<Option<Connector>> {
+    let tls_options = TLS_OPTIONS.get_or_init(TlsOptions::default);
+    if !tls_options.insecure && tls_options.ca_cert_pem.is_none() {
+        return Ok(None);
+    }
+
+    let mut builder = native_tls::TlsConnector::builder();
+    builder.danger_accept_invalid_certs(tls_options.insecure);
+    if let Some(ref pem) = tls_options.ca_cert_pem {
+        builder.add_root_certificate(native_tls::Certificate::from_pem(pem)?);
+    }
+    Ok(Some(Connector::NativeTls(builder.build()?)))
+}
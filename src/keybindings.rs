@@ -0,0 +1,146 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// What a key does. Digit keys always set the volume, so they can't be bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Stats,
+    Reconnect,
+    TogglePause,
+    SeekToLive,
+    ToggleFavorite,
+    NextFavorite,
+    PickFavorite,
+    NextStation,
+    Copy,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    Quit,
+    Help,
+}
+
+/// The `keybindings` section in the config file, like `{ "quit": "x", "mute": "m" }`.
+/// Actions not specified keep the default keys.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub stats: char,
+    pub reconnect: char,
+    pub toggle_pause: char,
+    pub seek_to_live: char,
+    pub toggle_favorite: char,
+    pub next_favorite: char,
+    pub pick_favorite: char,
+    pub next_station: char,
+    pub copy: char,
+    pub volume_up: char,
+    pub volume_down: char,
+    pub mute: char,
+    pub quit: char,
+    pub help: char,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            stats: 'i',
+            reconnect: 'r',
+            toggle_pause: ' ',
+            seek_to_live: 'l',
+            toggle_favorite: 'f',
+            next_favorite: 'n',
+            pick_favorite: 'p',
+            next_station: 's',
+            copy: 'c',
+            volume_up: '+',
+            volume_down: '-',
+            mute: 'm',
+            quit: 'q',
+            help: 'h',
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Map keys to actions. Fails if a key is bound to more than one action, or is a digit.
+    pub fn to_lookup_table(&self) -> Result<HashMap<char, Action>> {
+        // The name is kept to report conflicts
+        let mut lookup_table: HashMap<char, (&str, Action)> = HashMap::new();
+
+        for (name, key, action) in self.bindings() {
+            if key.is_ascii_digit() {
+                bail!("Cannot bind \"{key}\" to {name} in keybindings config, digit keys are for volume");
+            }
+            if let Some((existing_name, _)) = lookup_table.insert(key, (name, action)) {
+                bail!(
+                    "Key \"{key}\" is bound to both {existing_name} and {name} in keybindings config"
+                );
+            }
+        }
+
+        Ok(lookup_table
+            .into_iter()
+            .map(|(key, (_, action))| (key, action))
+            .collect())
+    }
+
+    // (Name in the config file, key, action)
+    const fn bindings(&self) -> [(&'static str, char, Action); 14] {
+        [
+            ("stats", self.stats, Action::Stats),
+            ("reconnect", self.reconnect, Action::Reconnect),
+            ("toggle_pause", self.toggle_pause, Action::TogglePause),
+            ("seek_to_live", self.seek_to_live, Action::SeekToLive),
+            (
+                "toggle_favorite",
+                self.toggle_favorite,
+                Action::ToggleFavorite,
+            ),
+            ("next_favorite", self.next_favorite, Action::NextFavorite),
+            ("pick_favorite", self.pick_favorite, Action::PickFavorite),
+            ("next_station", self.next_station, Action::NextStation),
+            ("copy", self.copy, Action::Copy),
+            ("volume_up", self.volume_up, Action::VolumeUp),
+            ("volume_down", self.volume_down, Action::VolumeDown),
+            ("mute", self.mute, Action::Mute),
+            ("quit", self.quit, Action::Quit),
+            ("help", self.help, Action::Help),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_keys_do_not_conflict() {
+        let lookup_table = KeyBindings::default().to_lookup_table().unwrap();
+        assert_eq!(lookup_table.len(), 14);
+        assert_eq!(lookup_table[&'s'], Action::NextStation);
+        assert_eq!(lookup_table[&'c'], Action::Copy);
+    }
+
+    #[test]
+    fn conflict_names_both_actions() {
+        let key_bindings = KeyBindings {
+            copy: 'q',
+            ..Default::default()
+        };
+        assert_eq!(
+            key_bindings.to_lookup_table().unwrap_err().to_string(),
+            "Key \"q\" is bound to both copy and quit in keybindings config"
+        );
+    }
+
+    #[test]
+    fn digit_keys_cannot_be_bound() {
+        let key_bindings = KeyBindings {
+            next_station: '5',
+            ..Default::default()
+        };
+        assert!(key_bindings.to_lookup_table().is_err());
+    }
+}
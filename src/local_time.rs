@@ -0,0 +1,245 @@
+use anyhow::{anyhow, Context};
+use std::str::FromStr;
+
+/// Local date and time, read from the OS in the system timezone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalDateTime {
+    pub year: i32,
+    pub month: u32, // 1-12
+    pub day: u32,   // 1-31
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub utc_offset_seconds: i64,
+}
+
+impl LocalDateTime {
+    pub fn now() -> Self {
+        imp::now()
+    }
+
+    pub const fn seconds_since_midnight(&self) -> u32 {
+        self.hour * 3600 + self.minute * 60 + self.second
+    }
+
+    /// Like "07:30:00"
+    pub fn format_time(&self) -> String {
+        format!("{:02}:{:02}:{:02}", self.hour, self.minute, self.second)
+    }
+
+    /// ISO 8601, like "2022-10-14T07:30:00+08:00"
+    pub fn format_iso8601(&self) -> String {
+        let offset_sign = if self.utc_offset_seconds < 0 {
+            '-'
+        } else {
+            '+'
+        };
+        let offset_minutes = self.utc_offset_seconds.abs() / 60;
+        format!(
+            "{:04}-{:02}-{:02}T{}{}{:02}:{:02}",
+            self.year,
+            self.month,
+            self.day,
+            self.format_time(),
+            offset_sign,
+            offset_minutes / 60,
+            offset_minutes % 60
+        )
+    }
+}
+
+/// A time of day like "07:30", parsed from user input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeOfDay {
+    pub hour: u32,
+    pub minute: u32,
+}
+
+impl TimeOfDay {
+    /// Seconds from now until the next time the clock shows this time of day (today or tomorrow)
+    pub fn seconds_until_next(&self) -> u64 {
+        let now = LocalDateTime::now().seconds_since_midnight() as i64;
+        let target = (self.hour * 3600 + self.minute * 60) as i64;
+        let seconds_until = target - now;
+        if seconds_until > 0 {
+            seconds_until as u64
+        } else {
+            (seconds_until + 24 * 3600) as u64
+        }
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow!("Invalid time \"{s}\", expected HH:MM like \"07:30\"");
+
+        let (hour, minute) = s.trim().split_once(':').with_context(invalid)?;
+        let hour: u32 = hour
+            .parse()
+            .ok()
+            .filter(|h| *h < 24)
+            .with_context(invalid)?;
+        let minute: u32 = minute
+            .parse()
+            .ok()
+            .filter(|m| *m < 60)
+            .with_context(invalid)?;
+
+        Ok(Self { hour, minute })
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::LocalDateTime;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    pub fn now() -> LocalDateTime {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs()) as libc::time_t;
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        unsafe {
+            libc::localtime_r(&timestamp, &mut tm);
+        }
+
+        LocalDateTime {
+            year: tm.tm_year + 1900,
+            month: (tm.tm_mon + 1) as u32,
+            day: tm.tm_mday as u32,
+            hour: tm.tm_hour as u32,
+            minute: tm.tm_min as u32,
+            second: tm.tm_sec as u32,
+            utc_offset_seconds: tm.tm_gmtoff as i64,
+        }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use super::LocalDateTime;
+
+    // https://learn.microsoft.com/en-us/windows/win32/api/minwinbase/ns-minwinbase-systemtime
+    #[repr(C)]
+    #[derive(Default)]
+    #[allow(dead_code)]
+    struct SystemTime {
+        year: u16,
+        month: u16,
+        day_of_week: u16,
+        day: u16,
+        hour: u16,
+        minute: u16,
+        second: u16,
+        milliseconds: u16,
+    }
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetLocalTime(system_time: *mut SystemTime);
+        fn GetSystemTime(system_time: *mut SystemTime);
+    }
+
+    pub fn now() -> LocalDateTime {
+        let mut local = SystemTime::default();
+        let mut utc = SystemTime::default();
+        unsafe {
+            GetLocalTime(&mut local);
+            GetSystemTime(&mut utc);
+        }
+
+        // Round to minutes, since the two calls above are not made at exactly the same time
+        let utc_offset_minutes =
+            (seconds_since_epoch(&local) - seconds_since_epoch(&utc) + 30).div_euclid(60);
+
+        LocalDateTime {
+            year: local.year as i32,
+            month: local.month as u32,
+            day: local.day as u32,
+            hour: local.hour as u32,
+            minute: local.minute as u32,
+            second: local.second as u32,
+            utc_offset_seconds: utc_offset_minutes * 60,
+        }
+    }
+
+    fn seconds_since_epoch(t: &SystemTime) -> i64 {
+        // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+        let (month, year) = (t.month as i64, t.year as i64 - (t.month <= 2) as i64);
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + t.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        let days = era * 146097 + day_of_era - 719468;
+
+        days * 86400 + t.hour as i64 * 3600 + t.minute as i64 * 60 + t.second as i64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date_time(utc_offset_seconds: i64) -> LocalDateTime {
+        LocalDateTime {
+            year: 2022,
+            month: 10,
+            day: 4,
+            hour: 7,
+            minute: 5,
+            second: 9,
+            utc_offset_seconds,
+        }
+    }
+
+    #[test]
+    fn format_iso8601_of_whole_hour_offsets() {
+        assert_eq!(date_time(0).format_iso8601(), "2022-10-04T07:05:09+00:00");
+        assert_eq!(
+            date_time(8 * 3600).format_iso8601(),
+            "2022-10-04T07:05:09+08:00"
+        );
+        assert_eq!(
+            date_time(-5 * 3600).format_iso8601(),
+            "2022-10-04T07:05:09-05:00"
+        );
+    }
+
+    #[test]
+    fn format_iso8601_of_partial_hour_offsets() {
+        // India, Nepal and the Marquesas Islands
+        assert_eq!(
+            date_time(5 * 3600 + 30 * 60).format_iso8601(),
+            "2022-10-04T07:05:09+05:30"
+        );
+        assert_eq!(
+            date_time(5 * 3600 + 45 * 60).format_iso8601(),
+            "2022-10-04T07:05:09+05:45"
+        );
+        assert_eq!(
+            date_time(-(9 * 3600 + 30 * 60)).format_iso8601(),
+            "2022-10-04T07:05:09-09:30"
+        );
+        // Less than an hour behind UTC keeps the sign
+        assert_eq!(
+            date_time(-30 * 60).format_iso8601(),
+            "2022-10-04T07:05:09-00:30"
+        );
+    }
+
+    #[test]
+    fn parse_time_of_day() {
+        assert_eq!(
+            "07:30".parse::<TimeOfDay>().unwrap(),
+            TimeOfDay {
+                hour: 7,
+                minute: 30
+            }
+        );
+        assert!("24:00".parse::<TimeOfDay>().is_err());
+        assert!("07:60".parse::<TimeOfDay>().is_err());
+        assert!("0730".parse::<TimeOfDay>().is_err());
+    }
+}
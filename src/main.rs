@@ -1,125 +1,733 @@
 mod args;
+mod art_cache;
+mod bench;
+mod check;
+mod circuit_breaker;
+mod command;
+mod config;
+mod downmix;
+mod effective_config;
+mod equalizer;
+mod exit_code;
+mod http_client;
+mod keybindings;
+mod local_time;
+mod message_dump;
 mod model;
 mod mp3_stream_decoder;
+mod now_playing_file;
+mod pcm_out;
 mod player;
+mod playlist;
+mod preferred_stations;
+mod schema;
+mod song_events;
+mod song_info;
+mod station_cache;
+mod station_filter;
+mod stats;
+mod stream_buffer;
+mod stream_resume;
 mod terminal;
+mod theme;
 mod update_checker;
 mod utils;
+mod web_ui;
+mod webhook;
 
 use anyhow::{anyhow, Context, Result};
-use args::Args;
-use clap::Parser;
+use args::{Args, Bell, ErrorFormat, ListenersFormat, TimestampFormat};
+use circuit_breaker::CircuitBreaker;
+use clap::{CommandFactory, FromArgMatches};
 use colored::Colorize;
+use command::Command;
+use config::{Config, CONFIG};
+use equalizer::EqGains;
+use exit_code::{ExitCode, ResultExt};
 use futures_util::StreamExt;
 use indicatif::{ProgressBar, ProgressState, ProgressStyle};
-use inquire::Select;
-use model::{CodeRadioMessage, Remote};
+use inquire::{InquireError, Select};
+use keybindings::Action;
+use local_time::{LocalDateTime, TimeOfDay};
+use model::{CodeRadioMessage, Remote, Song};
+use now_playing_file::NowPlayingFile;
 use player::Player;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
 use rodio::Source;
-use std::{fmt::Write, sync::Mutex, thread, time::Duration};
-use terminal::writeline;
-use tokio::{net::TcpStream, time::sleep};
+use song_events::{SongChange, SongChangeDispatcher};
+use song_info::{
+    get_extra_song_metadata, get_next_song, get_progress_bar_suffix,
+    get_station_name_from_api_message, get_template_values,
+};
+use station_filter::StationFilter;
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    io::{BufRead, IsTerminal},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+use terminal::{writeline, ProgressMode};
+use theme::{Role, Theme, ThemeName};
+use tokio::{
+    net::TcpStream,
+    task::JoinHandle,
+    time::{sleep, Interval, MissedTickBehavior},
+};
 use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use update_checker::Release;
+use utils::{Backoff, Template};
+use webhook::Webhook;
 
-const WEBSOCKET_API_URL: &str =
-    "wss://coderadio-admin.freecodecamp.org/api/live/nowplaying/coderadio";
-const REST_API_URL: &str = "https://coderadio-admin.freecodecamp.org/api/live/nowplaying/coderadio";
+// When the API reports an elapsed time slightly behind the progress bar, like when a message arrives late,
+// the progress bar pauses until it catches up instead of jumping backward
+const PROGRESS_BAR_MAX_PAUSED_SECONDS: u64 = 2;
+
+// Where song info values start, after labels like "Artist:"
+const SONG_FIELD_VALUE_COLUMN: usize = 12;
+
+// Used when connecting and reconnecting to the API
+const CONNECT_RETRY_ATTEMPTS: u32 = 3;
+const CONNECT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+const DEFAULT_BREAKER_THRESHOLD: u32 = 5;
+const DEFAULT_BREAKER_COOLDOWN_SECONDS: u64 = 30;
+
+const NO_MATCHING_STATION_ERROR: &str = "No station matches `--include` and `--exclude`";
+
+// How long to delay playing for the update check, if it's not done with the cache
+const UPDATE_CHECK_WAIT_TIMEOUT: Duration = Duration::from_millis(500);
+
+// `--follow` backoff between reconnects, which starts over after this long without errors
+const FOLLOW_INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+const FOLLOW_MAX_BACKOFF: Duration = Duration::from_secs(60);
+const FOLLOW_BACKOFF_RESET_AFTER: Duration = Duration::from_secs(5 * 60);
+
+// For `preferred_stations` in the config: how often to check for a failed stream,
+// and how often to check whether a more preferred station is reachable again
+const PREFERRED_STATIONS_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const PREFERRED_STATIONS_RECOVERY_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+// `--buffer-status` is highlighted below this fill, when the stream is close to stuttering
+const BUFFER_STATUS_LOW_FILL: f32 = 0.25;
+
+const LOGO: &str = "
+ ██████╗ ██████╗ ██████╗ ███████╗    ██████╗  █████╗ ██████╗ ██╗ ██████╗ 
+██╔════╝██╔═══██╗██╔══██╗██╔════╝    ██╔══██╗██╔══██╗██╔══██╗██║██╔═══██╗
+██║     ██║   ██║██║  ██║█████╗      ██████╔╝███████║██║  ██║██║██║   ██║
+██║     ██║   ██║██║  ██║██╔══╝      ██╔══██╗██╔══██║██║  ██║██║██║   ██║
+╚██████╗╚██████╔╝██████╔╝███████╗    ██║  ██║██║  ██║██████╔╝██║╚██████╔╝
+ ╚═════╝ ╚═════╝ ╚═════╝ ╚══════╝    ╚═╝  ╚═╝╚═╝  ╚═╝╚═════╝ ╚═╝ ╚═════╝ ";
+
+// Displayed instead of `LOGO` when the terminal is too narrow
+const COMPACT_LOGO: &str = "
+>>= CODE RADIO =<<";
 
 static PLAYER: Mutex<Option<Player>> = Mutex::new(None);
 static PROGRESS_BAR: Mutex<Option<ProgressBar>> = Mutex::new(None);
+static LISTEN_URL: Mutex<Option<String>> = Mutex::new(None);
+static CURRENT_STATION: Mutex<Option<Remote>> = Mutex::new(None);
+static STATIONS: Mutex<Vec<Remote>> = Mutex::new(Vec::new()); // Updated on every websocket message
+static LAST_MESSAGE: Mutex<Option<CodeRadioMessage>> = Mutex::new(None); // For `--web-port` and listening stats
+static CIRCUIT_BREAKER: Mutex<CircuitBreaker> = Mutex::new(CircuitBreaker::new(
+    DEFAULT_BREAKER_THRESHOLD,
+    Duration::from_secs(DEFAULT_BREAKER_COOLDOWN_SECONDS),
+)); // Guards websocket connects. Set from `--breaker-threshold` and `--breaker-cooldown`.
+static STRICT_PARSING: Mutex<bool> = Mutex::new(false); // Set from `--strict`
+static SPLIT_TITLE: Mutex<bool> = Mutex::new(false); // Set from `--split-title`
+static PRINT_STATS_ON_EXIT: Mutex<bool> = Mutex::new(false); // Set from `--print-stats-on-exit`
+static ERROR_FORMAT: Mutex<ErrorFormat> = Mutex::new(ErrorFormat::Pretty); // Set from `--error-format`
+static MONO_PROGRESS_SUFFIX: Mutex<Option<String>> = Mutex::new(None); // The listener count with `--mono-progress`, where the progress bar message is the song
+static BUFFER_STATUS: Mutex<Option<String>> = Mutex::new(None); // Like " · buf:85%" with `--buffer-status`, updated by `tick_progress_bar()`
+static LAST_ELAPSED_SECONDS: Mutex<Option<(Instant, u64)>> = Mutex::new(None); // When the API last reported the elapsed seconds
+
+// Set by command line args
+struct SongInfoDisplayOptions {
+    show_peak_listeners: bool,
+    song_template: Option<Template>, // `None` for the default layout
+    progress_mode: ProgressMode,
+    progress_chars: String,     // Validated on startup
+    refresh_interval: Duration, // Min. interval between progress bar repaints
+    listeners_format: ListenersFormat,
+    timestamp_format: Option<TimestampFormat>,
+    show_next: bool,
+    mono_progress: bool, // `--mono-progress` with the animated progress bar
+}
+
+// Kept across calls to `update_song_info_on_screen`
+#[derive(Default)]
+struct SongInfoDisplayState {
+    last_next_song_id: Option<String>,
+    last_segment: Option<String>,
+    last_listener_count: Option<i64>,
+    peak_listener_count: i64,
+}
 
 #[tokio::main]
 async fn main() {
     terminal::enable_color_on_windows();
-    let _terminal_clean_up_helper = terminal::create_clean_up_helper(); // See the comments in "terminal" module
+    let terminal_clean_up_helper = terminal::create_clean_up_helper(); // See the comments in "terminal" module
 
-    if let Err(e) = start().await {
-        writeline!();
-        terminal::print_error(e);
-    }
+    let exit_code = match start().await {
+        Ok(()) => ExitCode::Success,
+        Err(e) => {
+            let exit_code = ExitCode::from_error(&e);
+            match *ERROR_FORMAT.lock().unwrap() {
+                ErrorFormat::Pretty => {
+                    writeline!();
+                    terminal::print_error(e);
+                }
+                ErrorFormat::Json => {
+                    let error = serde_json::json!({
+                        "error": format!("{e:#}"),
+                        "kind": exit_code.kind(),
+                        "code": exit_code as i32,
+                    });
+                    eprintln!("{error}");
+                }
+            }
+            exit_code
+        }
+    };
+    terminal_clean_up_helper.exit(exit_code);
 }
 
 async fn start() -> Result<()> {
-    let args = Args::parse();
+    let started_at = Instant::now(); // For `--max-runtime`
+
+    // The matches are kept for `--print-effective`
+    let matches = Args::command()
+        .try_get_matches()
+        .unwrap_or_else(|e| exit_on_args_error(&e));
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| exit_on_args_error(&e));
+
+    if args.no_color {
+        colored::control::set_override(false);
+    }
+    *ERROR_FORMAT.lock().unwrap() = args.error_format;
+    *STRICT_PARSING.lock().unwrap() = args.strict;
+    *SPLIT_TITLE.lock().unwrap() = args.split_title;
+    *PRINT_STATS_ON_EXIT.lock().unwrap() = args.print_stats_on_exit;
+
+    // The prompt can't be canceled there
+    #[cfg(not(unix))]
+    if args.select_timeout.is_some() {
+        return Err(anyhow!("--select-timeout is not supported on Windows"))
+            .exit_code(ExitCode::BadArguments);
+    }
+
+    let config = Config::load().exit_code(ExitCode::BadArguments)?;
+    // An explicit `--theme` wins. Colors are off anyway with `--no-color`, so don't ask the terminal.
+    let theme_name = args
+        .theme
+        .or_else(|| {
+            (args.theme_from_terminal && !args.no_color).then(theme::detect_from_terminal)?
+        })
+        .unwrap_or(ThemeName::Default);
+    theme::set_theme(
+        Theme::from_name(theme_name)
+            .with_config(&config.theme)
+            .exit_code(ExitCode::BadArguments)?,
+    );
+    let key_lookup_table = config
+        .keybindings
+        .to_lookup_table()
+        .exit_code(ExitCode::BadArguments)?;
+    if args.print_effective {
+        writeline!(
+            "{}",
+            effective_config::to_json(&config, &matches).exit_code(ExitCode::BadArguments)?
+        );
+        return Ok(());
+    }
+    *CONFIG.lock().unwrap() = config;
+    http_client::set_user_agent(args.user_agent.clone());
+    http_client::set_api_headers(args.api_key.as_deref(), &args.header)
+        .exit_code(ExitCode::BadArguments)?;
+    http_client::set_tls_options(args.insecure, args.ca_cert.as_deref())
+        .exit_code(ExitCode::BadArguments)?;
+    if let Some(ref api_url) = args.api_url {
+        http_client::set_api_url(api_url).exit_code(ExitCode::BadArguments)?;
+    }
+    if args.insecure {
+        writeline!(
+            "{}",
+            theme::paint(
+                "Warning: TLS certificate verification is disabled by --insecure",
+                Role::Warning
+            )
+        );
+    }
+    let http_client = http_client::build_http_client()?;
+
+    if args.check {
+        let ok = check::check_connectivity(
+            &http_client,
+            http_client::rest_api_url(),
+            http_client::websocket_api_url(),
+        )
+        .await;
+        // Exit directly. The terminal is never put into raw mode in this mode, so there's nothing to clean up.
+        if ok {
+            ExitCode::Success.exit()
+        } else {
+            ExitCode::NetworkFailure.exit()
+        }
+    }
+
+    if args.list_hosts {
+        for host_name in player::get_available_host_names() {
+            writeline!("{host_name}");
+        }
+        return Ok(());
+    }
+
+    if args.clear_cache {
+        let freed_bytes = art_cache::clear().await?;
+        station_cache::remove_cache_file().await?;
+        update_checker::clear_cache()?;
+        writeline!(
+            "Cleared the caches of album art, stations and releases ({} KB)",
+            freed_bytes / 1024
+        );
+        return Ok(());
+    }
+
+    art_cache::set_max_size_mb(args.art_cache_size);
+    player::set_audio_retries(args.audio_retries);
+    player::set_volume_curve(args.volume_curve);
+    if let Some(channels) = args.channels {
+        player::set_channels(channels);
+    }
+    player::set_eq_gains(
+        EqGains::from_preset(args.eq, &CONFIG.lock().unwrap().equalizer)
+            .exit_code(ExitCode::BadArguments)?,
+    );
+    if let Some(ref path) = args.pcm_out {
+        pcm_out::enable(path.clone(), args.pcm_format);
+    }
+    if let Some(seconds) = args.buffer_ahead_seconds {
+        player::set_buffer_ahead_seconds(seconds);
+    }
+    if args.buffer_status {
+        BUFFER_STATUS.lock().unwrap().replace(String::new());
+    }
+    if let Some(ref audio_host) = args.audio_host {
+        player::set_audio_host(audio_host).exit_code(ExitCode::BadArguments)?;
+    }
+
+    if let Some(ref file_path) = args.dump_messages {
+        message_dump::enable(file_path.as_deref()).exit_code(ExitCode::BadArguments)?;
+    }
+
+    if args.bench {
+        return bench::run(http_client::websocket_api_url()).await;
+    }
+
+    if args.oneshot {
+        let message = get_message_from_rest_api(&http_client).await?;
+        let station_name = get_station_name_from_api_message(&message);
+        let template_values = get_template_values(&message, &station_name);
+        writeline!(
+            "{}",
+            Template::parse(&args.oneshot_format).render(&template_values)
+        );
+        // Exit directly. The terminal is never put into raw mode in this mode, so there's nothing to clean up.
+        ExitCode::Success.exit();
+    }
 
-    if args.volume > 9 {
-        return Err(anyhow!("Volume must be between 0 and 9"));
+    if let Some(ref path) = args.export_m3u {
+        let station_filter = StationFilter::new(&args.include, &args.exclude);
+        let stations = station_filter
+            .apply(fetch_stations_with_retries(&http_client, args.rest_attempts).await?);
+        playlist::write_m3u(path, &stations).await?;
+        writeline!(
+            "Saved {} stations to \"{}\"",
+            stations.len(),
+            path.display()
+        );
+        return Ok(());
+    }
+
+    if args.print_url {
+        // Only the url goes to stdout, so that it can be piped to other players
+        terminal::set_output(Box::new(std::io::stderr()));
+        let station_filter = StationFilter::new(&args.include, &args.exclude);
+        let selected_station = if args.select_station {
+            select_station(&http_client, &args, &station_filter).await?
+        } else {
+            None
+        };
+        let message = get_message_from_rest_api(&http_client).await?;
+        let stations = station_filter.apply(message.get_stations());
+        let listen_url = resolve_listen_url(
+            &args,
+            &message,
+            &stations,
+            selected_station.as_ref(),
+            &station_filter,
+        )?;
+        terminal::write_stdout_line(&listen_url);
+        return Ok(());
+    }
+
+    if args.dismiss_update {
+        match update_checker::get_new_release(http_client::build_external_http_client()?).await? {
+            Some(new_release) => {
+                let mut config = CONFIG.lock().unwrap();
+                config.dismissed_release_version = Some(new_release.version.clone());
+                config.save()?;
+                writeline!("Won't notify about version {} again", new_release.version);
+            }
+            None => writeline!("No new version available"),
+        }
+        return Ok(());
     }
 
-    start_playing(args).await?;
+    let remaining_runtime = args
+        .max_runtime
+        .map(|max_runtime| max_runtime.saturating_sub(started_at.elapsed()));
+    let exit_when_idle = args.exit_when_idle;
+
+    // Playing is stopped by dropping it. `main()` cleans up the terminal.
+    tokio::select! {
+        result = start_playing(args, http_client, key_lookup_table) => result?,
+        () = sleep_if_some(remaining_runtime) => quit_playing("Reached max runtime, quitting"),
+        () = wait_until_idle_for(exit_when_idle) => quit_playing(&format!(
+            "No audio for {} seconds, quitting",
+            exit_when_idle.unwrap_or_default().as_secs()
+        )),
+    }
+    print_stats_on_exit();
 
     Ok(())
 }
 
-async fn start_playing(args: Args) -> Result<()> {
-    let mut update_checking_task_holder = Some(tokio::spawn(update_checker::get_new_release()));
+fn exit_on_args_error(e: &clap::Error) -> ! {
+    let _ = e.print();
+    // Exit directly. The terminal is never put into raw mode before this, so there's nothing to clean up.
+    if e.use_stderr() {
+        ExitCode::BadArguments.exit()
+    } else {
+        ExitCode::Success.exit() // `--help` or `--version`
+    }
+}
+
+// Never completes if `None`
+async fn sleep_if_some(duration: Option<Duration>) {
+    match duration {
+        Some(duration) => sleep(duration).await,
+        None => std::future::pending().await,
+    }
+}
+
+// For `--exit-when-idle`. Completes when no audio has played for the duration since playing started,
+// like when paused, buffering, or without an audio device. Never completes if `None`.
+async fn wait_until_idle_for(duration: Option<Duration>) {
+    let Some(duration) = duration else {
+        return std::future::pending().await;
+    };
+
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    let mut idle_since: Option<Instant> = None;
+    loop {
+        interval.tick().await;
+        // Not idle while selecting a station or waiting for `--start-at`
+        if LISTEN_URL.lock().unwrap().is_none() {
+            continue;
+        }
+
+        let is_idle = PLAYER
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(true, |player| player.is_paused() || player.is_buffering());
+        if !is_idle {
+            idle_since = None;
+        } else if idle_since.get_or_insert_with(Instant::now).elapsed() >= duration {
+            return;
+        }
+    }
+}
+
+fn quit_playing(message: &str) {
+    if let Some(progress_bar) = PROGRESS_BAR.lock().unwrap().as_ref() {
+        progress_bar.finish_and_clear();
+    }
+    writeline!("{message}");
+}
+
+async fn start_playing(
+    args: Args,
+    http_client: reqwest::Client,
+    key_lookup_table: HashMap<char, Action>,
+) -> Result<()> {
+    if let Some(ref url) = args.url {
+        return play_stream_url(&args, &http_client, url, key_lookup_table).await;
+    }
+
+    let external_http_client = http_client::build_external_http_client()?;
+    let mut update_checking_task_holder = Some(tokio::spawn(update_checker::get_new_release(
+        external_http_client.clone(),
+    )));
+
+    *CIRCUIT_BREAKER.lock().unwrap() = CircuitBreaker::new(
+        args.breaker_threshold,
+        Duration::from_secs(args.breaker_cooldown),
+    );
+
+    if let Some(port) = args.web_port {
+        let listener = web_ui::bind(port).await.exit_code(ExitCode::BadArguments)?;
+        tokio::spawn(web_ui::serve(listener, http_client.clone()));
+    }
+
+    let mut song_change_dispatcher =
+        SongChangeDispatcher::new(Duration::from_secs(args.min_play_seconds));
+
+    if let Some(ref url) = args.webhook {
+        let webhook = Webhook::try_new(external_http_client.clone(), url, &args.webhook_header)
+            .exit_code(ExitCode::BadArguments)?;
+        song_change_dispatcher.register(webhook);
+    }
+    if let Some(ref path) = args.now_playing_file {
+        song_change_dispatcher.register(NowPlayingFile::new(path.clone()));
+    }
 
     display_welcome_message(&args);
 
+    // Initialize the audio device in background while the user is selecting a station.
+    // If the user cancels, the task is detached and dropped on exit.
+    let player_init_task = (args.select_station && !args.no_audio)
+        .then(|| tokio::task::spawn_blocking(Player::try_new));
+
+    let station_filter = StationFilter::new(&args.include, &args.exclude);
     let mut selected_station: Option<Remote> = None;
 
     if args.select_station {
-        let station = select_station().await?;
-        selected_station = Some(station);
+        selected_station = select_station(&http_client, &args, &station_filter).await?;
     }
 
-    // Connect websocket in background while creating `Player` to improve startup speed
-    let websocket_connect_task = tokio::spawn(tokio_tungstenite::connect_async(WEBSOCKET_API_URL));
+    if let Some(start_at) = args.start_at {
+        wait_until(start_at).await?;
+    }
 
-    let loading_spinner = ProgressBar::new_spinner()
-        .with_style(ProgressStyle::with_template("{spinner} {msg}")?)
-        .with_message("Initializing audio device...");
+    // Connect websocket in background while creating `Player` to improve startup speed.
+    // The current attempt number is sent to the loading spinner.
+    let (connect_attempt_sender, mut connect_attempt_receiver) = tokio::sync::watch::channel(1);
+    let mut websocket_connect_task = tokio::spawn(utils::retry(
+        CONNECT_RETRY_ATTEMPTS,
+        CONNECT_RETRY_DELAY,
+        move |attempt| {
+            let _ = connect_attempt_sender.send(attempt);
+        },
+        connect_websocket,
+    ));
+
+    let progress_mode = args.progress.resolve();
+
+    let refresh_interval = Duration::from_millis(args.refresh_rate);
+    let loading_spinner =
+        ProgressBar::with_draw_target(None, progress_mode.draw_target(refresh_interval))
+            .with_style(ProgressStyle::with_template("{spinner} {msg}")?)
+            .with_message(if args.no_audio {
+                "Connecting..."
+            } else {
+                "Initializing audio device..."
+            });
     loading_spinner.enable_steady_tick(Duration::from_millis(120));
 
-    // Creating a `Player` might be time consuming. It might take several seconds on first run.
-    match Player::try_new() {
-        Ok(mut player) => {
-            player.set_volume(args.volume);
-            PLAYER.lock().unwrap().replace(player);
+    let mut follow_backoff = Backoff::new(
+        FOLLOW_INITIAL_BACKOFF,
+        FOLLOW_MAX_BACKOFF,
+        FOLLOW_BACKOFF_RESET_AFTER,
+    );
+
+    // Startup: initialize player, connect and receive the first message.
+    // `--startup-timeout` only applies to this part, not to steady-state playback.
+    let startup = async {
+        // `PLAYER` stays `None`, like when the audio device fails
+        let player = if args.no_audio {
+            None
+        } else {
+            Some(
+                create_player_with_retries(args.audio_retries, &loading_spinner, player_init_task)
+                    .await,
+            )
+        };
+        match player {
+            None => {}
+            Some(Ok(mut player)) => {
+                player.set_volume(if args.fade_in.is_some() {
+                    args.volume.min(1)
+                } else {
+                    args.volume
+                });
+                PLAYER.lock().unwrap().replace(player);
+            }
+            Some(Err(e)) => {
+                loading_spinner.suspend(|| {
+                    terminal::print_error(format!("{e:#}"));
+                    writeline!("Playing without audio. Will keep trying to initialize the audio device in background.");
+                    writeline!();
+                });
+                tokio::spawn(recover_player(args.volume));
+            }
         }
-        Err(e) => {
-            terminal::print_error(e);
-            writeline!();
+
+        let connect = async {
+            let mut websocket_stream = loop {
+                loading_spinner.set_message(match *connect_attempt_receiver.borrow_and_update() {
+                    1 => "Connecting...".to_owned(),
+                    attempt => {
+                        format!("Connecting (attempt {attempt}/{CONNECT_RETRY_ATTEMPTS})...")
+                    }
+                });
+                tokio::select! {
+                    result = &mut websocket_connect_task => break result??,
+                    // Fails when the task finishes and drops the sender. Then the task branch is ready.
+                    Ok(()) = connect_attempt_receiver.changed() => {}
+                }
+            };
+
+            loading_spinner.set_message("Fetching song info...");
+            let first_message = get_next_websocket_message(&mut websocket_stream).await?;
+            Ok::<_, anyhow::Error>((websocket_stream, first_message))
+        };
+
+        match connect.await {
+            Err(e) if args.follow => {
+                loading_spinner.finish_and_clear();
+                Ok(connect_websocket_forever(e, &mut follow_backoff).await)
+            }
+            result => result,
         }
+    };
+
+    let (mut websocket_stream, first_message) = match args.startup_timeout {
+        Some(seconds) => match tokio::time::timeout(Duration::from_secs(seconds), startup).await {
+            Ok(result) => result?,
+            Err(elapsed) => {
+                loading_spinner.finish_and_clear();
+                return Err(anyhow::Error::new(elapsed)
+                    .context(format!("Startup timed out after {seconds} seconds")));
+            }
+        },
+        None => startup.await?,
+    };
+
+    let progress_chars = if args.ascii_bar {
+        theme::ASCII_PROGRESS_CHARS.to_owned()
+    } else {
+        CONFIG
+            .lock()
+            .unwrap()
+            .theme
+            .progress_chars
+            .clone()
+            .unwrap_or_else(|| theme::DEFAULT_PROGRESS_CHARS.to_owned())
+    };
+
+    let display_options = SongInfoDisplayOptions {
+        show_peak_listeners: args.show_peak_listeners,
+        progress_chars,
+        song_template: args.format.as_deref().map(Template::parse),
+        progress_mode,
+        refresh_interval,
+        listeners_format: args.listeners,
+        timestamp_format: args.timestamps,
+        show_next: args.show_next,
+        mono_progress: args.mono_progress && progress_mode.is_animated(),
+    };
+    if display_options.mono_progress {
+        MONO_PROGRESS_SUFFIX.lock().unwrap().replace(String::new());
     }
+    let mut display_state = SongInfoDisplayState::default();
 
-    loading_spinner.set_message("Connecting...");
+    tokio::spawn(tick_progress_bar());
+    if !progress_mode.is_animated() {
+        tokio::spawn(print_progress_status_periodically());
+    }
+    if args.stats {
+        tokio::spawn(print_stats_periodically());
+    }
+    tokio::spawn(track_listening_time());
 
-    let mut listen_url = None;
-    let mut last_song_id = String::new();
+    // Sent by the keyboard thread when "r" is pressed
+    let (reconnect_request_sender, mut reconnect_request_receiver) =
+        tokio::sync::mpsc::unbounded_channel::<()>();
+    let mut reconnect_request_sender = Some(reconnect_request_sender);
 
-    let (mut websocket_stream, _) = websocket_connect_task.await??;
-    tokio::spawn(tick_progress_bar());
+    let mut refresh_timer = args.reconnect_interval.map(|interval| {
+        let mut timer = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        timer
+    });
 
+    let mut pending_message = Some(first_message);
+    let mut is_first_song = true; // The first song is not a song change for `--bell`
     loop {
-        let message = get_next_websocket_message(&mut websocket_stream).await?;
-        if listen_url.is_none() {
+        let pending_commit_at = song_change_dispatcher.pending_commit_at();
+        let message_result = match pending_message.take() {
+            Some(message) => Ok(message),
+            None => tokio::select! {
+                message = get_next_websocket_message(&mut websocket_stream) => message,
+                // Without waiting for the next message, which may take a while
+                () = sleep_until_pending_commit(pending_commit_at) => {
+                    song_change_dispatcher.commit_pending();
+                    continue;
+                }
+                Some(()) = reconnect_request_receiver.recv() => {
+                    reconnect(&mut websocket_stream).await
+                }
+                () = tick_refresh_timer(&mut refresh_timer) => {
+                    refresh_websocket(&mut websocket_stream).await
+                }
+            },
+        };
+        let message = match message_result {
+            Ok(message) => message,
+            Err(e) if args.follow => {
+                let (new_websocket_stream, message) =
+                    connect_websocket_forever(e, &mut follow_backoff).await;
+                websocket_stream = new_websocket_stream;
+                message
+            }
+            Err(e) => return Err(e),
+        };
+        let stations = station_filter.apply(message.get_stations());
+        let previous_stations = std::mem::replace(&mut *STATIONS.lock().unwrap(), stations.clone());
+        // The previous list is empty before the first message
+        if args.notify_station_changes && !previous_stations.is_empty() {
+            print_station_changes(&previous_stations, &stations);
+        }
+
+        if LISTEN_URL.lock().unwrap().is_none() {
             // Start playing
             loading_spinner.finish_and_clear();
 
-            let stations = get_stations_from_api_message(&message);
-
-            let listen_url_value = match selected_station {
-                Some(ref station) => stations
-                    .iter()
-                    .find(|s| s.id == station.id)
-                    .context(anyhow!("Station with ID \"{}\" not found", station.id))?
-                    .url
-                    .clone(),
-                None => message.station.listen_url.clone(),
-            };
-
-            // Notify user if a new version is available
-            if let Some(update_checking_task) = update_checking_task_holder.take() {
-                if update_checking_task.is_finished() {
-                    if let Ok(Ok(Some(new_release))) = update_checking_task.await {
-                        writeline!(
-                            "{}",
-                            format!("New version available: {}", new_release.version)
-                                .bright_yellow()
-                        );
-                        writeline!("{}", new_release.url.bright_yellow());
-                        writeline!();
+            let listen_url_value = resolve_listen_url(
+                &args,
+                &message,
+                &stations,
+                selected_station.as_ref(),
+                &station_filter,
+            )?;
+
+            // Notify user if a new version is available.
+            // Usually the check is already done with the cache. If not, notify when it's done.
+            if let Some(mut update_checking_task) = update_checking_task_holder.take() {
+                match tokio::time::timeout(UPDATE_CHECK_WAIT_TIMEOUT, &mut update_checking_task)
+                    .await
+                {
+                    Ok(Ok(Ok(Some(new_release)))) => notify_new_release(&new_release),
+                    Ok(_) => {}
+                    Err(_) => {
+                        tokio::spawn(async move {
+                            if let Ok(Ok(Some(new_release))) = update_checking_task.await {
+                                print_above_progress_bar(|| notify_new_release(&new_release));
+                            }
+                        });
                     }
                 }
             }
@@ -128,31 +736,363 @@ async fn start_playing(args: Args) -> Result<()> {
                 .iter()
                 .find(|station| station.url == listen_url_value)
             {
-                writeline!("{}    {}", "Station:".bright_green(), station.name);
+                writeline!(
+                    "{}    {}",
+                    theme::paint("Station:", Role::Station),
+                    station.name
+                );
+                if let Some(preference) = describe_preference_level(station) {
+                    writeline!("{}", preference.bright_black());
+                }
+                CURRENT_STATION.lock().unwrap().replace(station.clone());
+                if let Err(e) = remember_last_station(station) {
+                    terminal::print_error(format!("{e:#}"));
+                }
+            }
+            if !CONFIG.lock().unwrap().preferred_stations.is_empty() {
+                tokio::spawn(watch_preferred_stations(http_client.clone()));
             }
 
-            if let Some(player) = PLAYER.lock().unwrap().as_ref() {
+            // Set `LISTEN_URL` before locking `PLAYER`. See `recover_player()`.
+            LISTEN_URL.lock().unwrap().replace(listen_url_value.clone());
+            if let Some(player) = PLAYER.lock().unwrap().as_mut() {
+                if args.start_paused {
+                    player.set_paused(true);
+                }
                 player.play(&listen_url_value);
             }
+            if args.start_paused {
+                print_start_paused_hint();
+            }
+
+            if let Some(fade_in_seconds) = args.fade_in {
+                tokio::spawn(fade_in_volume(
+                    args.volume,
+                    Duration::from_secs(fade_in_seconds),
+                ));
+            }
 
-            listen_url = Some(listen_url_value);
+            if std::io::stdin().is_terminal() {
+                let toggle_mute = args.toggle_mute;
+                let reconnect_request_sender = reconnect_request_sender.take().unwrap();
+                let key_lookup_table = key_lookup_table.clone();
+                thread::spawn(move || {
+                    handle_keyboard_events(toggle_mute, key_lookup_table, reconnect_request_sender)
+                });
+            } else {
+                thread::spawn(handle_stdin_commands);
+            }
+        }
 
-            thread::spawn(handle_keyboard_events);
+        let station_name = CURRENT_STATION
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|s| s.name.clone());
+        let song_change = song_change_dispatcher.dispatch(&message, station_name);
+        LAST_MESSAGE.lock().unwrap().replace(message.clone());
+
+        if song_change.is_some() {
+            stats::STATS.lock().unwrap().listening.add_song(
+                CURRENT_STATION.lock().unwrap().as_ref(),
+                &message.now_playing.song.artist,
+            );
+            if let Some(bell) = args.bell.filter(|_| !is_first_song) {
+                ring_bell(bell);
+            }
+            is_first_song = false;
         }
 
-        update_song_info_on_screen(message, &mut last_song_id);
+        update_song_info_on_screen(
+            message,
+            song_change.as_ref(),
+            &display_options,
+            &mut display_state,
+        );
     }
 }
 
-fn display_welcome_message(args: &Args) {
-    let logo = "
- ██████╗ ██████╗ ██████╗ ███████╗    ██████╗  █████╗ ██████╗ ██╗ ██████╗ 
-██╔════╝██╔═══██╗██╔══██╗██╔════╝    ██╔══██╗██╔══██╗██╔══██╗██║██╔═══██╗
-██║     ██║   ██║██║  ██║█████╗      ██████╔╝███████║██║  ██║██║██║   ██║
-██║     ██║   ██║██║  ██║██╔══╝      ██╔══██╗██╔══██║██║  ██║██║██║   ██║
-╚██████╗╚██████╔╝██████╔╝███████╗    ██║  ██║██║  ██║██████╔╝██║╚██████╔╝
- ╚═════╝ ╚═════╝ ╚═════╝ ╚══════╝    ╚═╝  ╚═╝╚═╝  ╚═╝╚═════╝ ╚═╝ ╚═════╝ ";
+// For `--url`. Play the stream without the API, so there's no song info. The progress bar only shows the elapsed time.
+async fn play_stream_url(
+    args: &Args,
+    http_client: &reqwest::Client,
+    url: &str,
+    key_lookup_table: HashMap<char, Action>,
+) -> Result<()> {
+    let parsed_url = reqwest::Url::parse(url)
+        .with_context(|| format!("Invalid stream URL \"{url}\""))
+        .exit_code(ExitCode::BadArguments)?;
+    if !matches!(parsed_url.scheme(), "http" | "https") {
+        return Err(anyhow!(
+            "Stream URL \"{url}\" must start with \"http://\" or \"https://\""
+        ))
+        .exit_code(ExitCode::BadArguments);
+    }
+
+    display_welcome_message(args);
+
+    let progress_mode = args.progress.resolve();
+    let refresh_interval = Duration::from_millis(args.refresh_rate);
+    let loading_spinner =
+        ProgressBar::with_draw_target(None, progress_mode.draw_target(refresh_interval))
+            .with_style(ProgressStyle::with_template("{spinner} {msg}")?)
+            .with_message("Connecting...");
+    loading_spinner.enable_steady_tick(Duration::from_millis(120));
+
+    // The player thread only retries when the stream fails, so check that it opens first
+    probe_mp3_stream(http_client, url).await?;
+
+    loading_spinner.set_message("Initializing audio device...");
+    let mut player = create_player_with_retries(args.audio_retries, &loading_spinner, None).await?;
+    player.set_volume(args.volume);
+    loading_spinner.finish_and_clear();
+
+    writeline!("{}     {}", theme::paint("Stream:", Role::Station), url);
+    writeline!();
+
+    // Set `LISTEN_URL` before locking `PLAYER`. See `recover_player()`.
+    LISTEN_URL.lock().unwrap().replace(url.to_owned());
+    if args.start_paused {
+        player.set_paused(true);
+        print_start_paused_hint();
+    }
+    player.play(url);
+    let progress_bar_prefix = get_progress_bar_prefix_from_player(Some(&player));
+    PLAYER.lock().unwrap().replace(player);
+
+    // No bar, since the stream has no end
+    let progress_bar_style =
+        ProgressStyle::with_template("{prefix}  {progress_info} - {msg}{buffer_status}")?
+            .with_key(
+                "progress_info",
+                |state: &ProgressState, write: &mut dyn Write| {
+                    let progress_info = get_progress_bar_progress_info(state.pos(), None);
+                    write!(write, "{progress_info}").unwrap();
+                },
+            )
+            .with_key("buffer_status", write_buffer_status);
+    let progress_bar =
+        ProgressBar::with_draw_target(Some(u64::MAX), progress_mode.draw_target(refresh_interval))
+            .with_style(progress_bar_style)
+            .with_prefix(progress_bar_prefix)
+            .with_message("LIVE");
+    progress_bar.tick();
+    *PROGRESS_BAR.lock().unwrap() = Some(progress_bar);
+    LAST_ELAPSED_SECONDS
+        .lock()
+        .unwrap()
+        .replace((Instant::now(), 0));
+
+    tokio::spawn(tick_progress_bar());
+    if !progress_mode.is_animated() {
+        tokio::spawn(print_progress_status_periodically());
+    }
+    if args.stats {
+        tokio::spawn(print_stats_periodically());
+    }
+
+    // Sent by the keyboard thread when "r" is pressed
+    let (reconnect_request_sender, mut reconnect_request_receiver) =
+        tokio::sync::mpsc::unbounded_channel::<()>();
+    if std::io::stdin().is_terminal() {
+        let toggle_mute = args.toggle_mute;
+        thread::spawn(move || {
+            handle_keyboard_events(toggle_mute, key_lookup_table, reconnect_request_sender)
+        });
+    } else {
+        drop(reconnect_request_sender);
+        thread::spawn(handle_stdin_commands);
+    }
+
+    // Play until quitting
+    while let Some(()) = reconnect_request_receiver.recv().await {
+        if let Some(player) = PLAYER.lock().unwrap().as_ref() {
+            player.reconnect();
+        }
+    }
+    std::future::pending().await
+}
+
+// Which station's stream to play, from `--select-station`, `--min-bitrate`, `--random`, etc.
+// `stations` are filtered by `--include` and `--exclude`.
+fn resolve_listen_url(
+    args: &Args,
+    message: &CodeRadioMessage,
+    stations: &[Remote],
+    selected_station: Option<&Remote>,
+    station_filter: &StationFilter,
+) -> Result<String> {
+    let preferred = preferred_stations::find(&CONFIG.lock().unwrap().preferred_stations, stations);
+    let listen_url = match selected_station {
+        Some(station) => stations
+            .iter()
+            .find(|s| s.id == station.id)
+            .context(anyhow!("Station with ID \"{}\" not found", station.id))?
+            .url
+            .clone(),
+        None if args.min_bitrate.is_some() || args.max_bitrate.is_some() => {
+            get_station_by_bitrate_range(stations, args.min_bitrate, args.max_bitrate)
+                .context(anyhow!(
+                    "No station with bitrate between {} and {} kbps",
+                    args.min_bitrate.unwrap_or(0),
+                    args.max_bitrate
+                        .map_or_else(|| "unlimited".to_owned(), |b| b.to_string())
+                ))
+                .exit_code(ExitCode::BadArguments)?
+                .url
+                .clone()
+        }
+        None if args.random => {
+            let station = pick_random_station(stations, args.favorites_only, args.seed)
+                .context("No station to pick from")?;
+            station.url.clone()
+        }
+        None if args.resume => {
+            let last_station_id = CONFIG.lock().unwrap().last_station_id;
+            match last_station_id.and_then(|id| stations.iter().find(|s| s.id == id)) {
+                Some(station) => station.url.clone(),
+                None => {
+                    if last_station_id.is_some() {
+                        writeline!(
+                            "{}",
+                            theme::paint(
+                                "The last played station no longer exists, playing the default station",
+                                Role::Warning
+                            )
+                        );
+                    }
+                    message.station.listen_url.clone()
+                }
+            }
+        }
+        // `watch_preferred_stations()` fails over if it's not reachable
+        None if !preferred.is_empty() => preferred[0].station.url.clone(),
+        // Play the first matching station if the default one is filtered out
+        None => station_filter
+            .default_station_url(stations, &message.station.listen_url)
+            .context(NO_MATCHING_STATION_ERROR)
+            .exit_code(ExitCode::BadArguments)?
+            .to_owned(),
+    };
+
+    Ok(listen_url)
+}
+
+// Show a countdown until the given local time.
+// The remaining time is recalculated from the wall clock every second,
+// so that it still works if the machine sleeps or the clock jumps during the wait.
+async fn wait_until(start_at: TimeOfDay) -> Result<()> {
+    let target_time = SystemTime::now() + Duration::from_secs(start_at.seconds_until_next());
+
+    let countdown_spinner =
+        ProgressBar::new_spinner().with_style(ProgressStyle::with_template("{spinner} {msg}")?);
+
+    loop {
+        let remaining = match target_time.duration_since(SystemTime::now()) {
+            Ok(remaining) if !remaining.is_zero() => remaining,
+            _ => break,
+        };
 
+        countdown_spinner.set_message(format!(
+            "Will start playing at {:02}:{:02}, {} left...",
+            start_at.hour,
+            start_at.minute,
+            utils::humanize_seconds_to_minutes_and_seconds(remaining.as_secs())
+        ));
+        countdown_spinner.tick();
+
+        sleep(remaining.min(Duration::from_secs(1))).await;
+    }
+
+    countdown_spinner.finish_and_clear();
+    Ok(())
+}
+
+// Raise the volume step by step from 1 to `target_volume`.
+// Stop if the volume is changed by user during fading in.
+async fn fade_in_volume(target_volume: u8, duration: Duration) {
+    if target_volume <= 1 {
+        return;
+    }
+
+    let step_interval = duration / (target_volume as u32 - 1);
+    let mut expected_volume = 1;
+
+    while expected_volume < target_volume {
+        sleep(step_interval).await;
+
+        let mut player_guard = PLAYER.lock().unwrap();
+        let Some(player) = player_guard.as_mut() else {
+            return;
+        };
+        if player.volume() != expected_volume {
+            return;
+        }
+        expected_volume += 1;
+        set_player_volume(player, expected_volume);
+    }
+}
+
+// Creating a `Player` might be time consuming. It might take several seconds on first run.
+// On some machines the audio device is not ready right after login, so retry a few times.
+// `first_attempt` is a task already initializing the player, if any
+async fn create_player_with_retries(
+    retries: u32,
+    loading_spinner: &ProgressBar,
+    mut first_attempt: Option<JoinHandle<Result<Player>>>,
+) -> Result<Player> {
+    let mut attempt = 0;
+
+    loop {
+        // In a blocking thread, so that `--startup-timeout` still works if the audio device hangs
+        let task = first_attempt
+            .take()
+            .unwrap_or_else(|| tokio::task::spawn_blocking(Player::try_new));
+        match task.await? {
+            Ok(player) => return Ok(player),
+            Err(e) => {
+                if attempt == retries {
+                    return Err(e.context(format!("Gave up after {} attempts", retries + 1)));
+                }
+                attempt += 1;
+                loading_spinner.set_message(format!(
+                    "Initializing audio device (retry {attempt}/{retries})..."
+                ));
+                sleep(Duration::from_secs(2)).await;
+            }
+        }
+    }
+}
+
+// Periodically try to initialize the audio device, so that audio can recover without a restart
+async fn recover_player(volume: u8) {
+    loop {
+        sleep(Duration::from_secs(10)).await;
+
+        // In a blocking thread, since it might take seconds and would stall the runtime
+        let Ok(Ok(mut player)) = tokio::task::spawn_blocking(Player::try_new).await else {
+            continue;
+        };
+        player.set_volume(volume);
+
+        // Hold the `PLAYER` lock while reading `LISTEN_URL`,
+        // so that the stream is played even if `LISTEN_URL` is set at the same time
+        let mut player_guard = PLAYER.lock().unwrap();
+        if let Some(listen_url) = LISTEN_URL.lock().unwrap().as_ref() {
+            player.play(listen_url);
+        }
+        let progress_bar_prefix = get_progress_bar_prefix_from_player(Some(&player));
+        player_guard.replace(player);
+        drop(player_guard);
+
+        if let Some(progress_bar) = PROGRESS_BAR.lock().unwrap().as_ref() {
+            progress_bar.set_prefix(progress_bar_prefix);
+        }
+        return;
+    }
+}
+
+fn display_welcome_message(args: &Args) {
     let app_name_and_version = format!("Code Radio CLI v{}", env!("CARGO_PKG_VERSION"));
     let help_command = format!("{} --help", utils::get_current_executable_name());
 
@@ -161,36 +1101,59 @@ fn display_welcome_message(args: &Args) {
 A command line music radio client for https://coderadio.freecodecamp.org
 GitHub: https://github.com/JasonWei512/code-radio-cli
 
-Press 0-9 to adjust volume. Press Ctrl+C to exit.
+{}
 Run {} to get more help.",
-        app_name_and_version.bright_green(),
-        help_command.bright_yellow()
+        theme::paint(&app_name_and_version, Role::Accent),
+        get_key_help(args.toggle_mute),
+        theme::paint(&help_command, Role::Warning)
     );
 
     if !args.no_logo {
-        writeline!("{}", logo);
+        writeline!("{}", get_logo_fitting_terminal());
         writeline!();
     }
     writeline!("{}", description);
     writeline!();
 }
 
+fn get_logo_fitting_terminal() -> &'static str {
+    let logo_width = LOGO
+        .lines()
+        .map(console::measure_text_width)
+        .max()
+        .unwrap_or(0);
+    match terminal::STDOUT.size_checked() {
+        Some((_rows, columns)) if (columns as usize) < logo_width => COMPACT_LOGO,
+        _ => LOGO,
+    }
+}
+
 async fn get_next_websocket_message(
     websocket_stream: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
 ) -> Result<CodeRadioMessage> {
     if let Some(Ok(message)) = websocket_stream.next().await {
         if let Ok(message_text) = message.into_text() {
-            if let Ok(code_radio_message) = serde_json::de::from_str(message_text.as_str()) {
-                return Ok(code_radio_message);
+            message_dump::dump(&message_text);
+            match parse_message(&message_text) {
+                Ok(code_radio_message) => return Ok(code_radio_message),
+                // Reconnecting wouldn't help
+                Err(e) if *STRICT_PARSING.lock().unwrap() => return Err(e),
+                Err(_) => {}
             }
         }
     }
 
     // Cannot get message from WebSocket. Try to reconnect.
+    reconnect_websocket_with_retries(websocket_stream).await
+}
 
-    let mut retry_count = 3;
+async fn reconnect_websocket_with_retries(
+    websocket_stream: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+) -> Result<CodeRadioMessage> {
+    let mut retry_count = CONNECT_RETRY_ATTEMPTS;
 
     loop {
+        stats::STATS.lock().unwrap().reconnect_count += 1;
         match reconnect_websocket_and_get_next_message(websocket_stream).await {
             Ok(result) => return Ok(result),
             Err(error) => {
@@ -198,55 +1161,257 @@ async fn get_next_websocket_message(
                 if retry_count == 0 {
                     return Err(error);
                 }
-                sleep(Duration::from_secs(1)).await;
+                sleep(CONNECT_RETRY_DELAY).await;
             }
         }
     }
 }
 
-async fn reconnect_websocket_and_get_next_message(
+// Reconnect both the websocket and the audio stream on user request, like after switching networks
+async fn reconnect(
     websocket_stream: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
 ) -> Result<CodeRadioMessage> {
-    let _ = websocket_stream.close(None).await;
-    let (new_websocket_stream, _) = tokio_tungstenite::connect_async(WEBSOCKET_API_URL).await?;
-    *websocket_stream = new_websocket_stream;
+    if let Some(progress_bar) = PROGRESS_BAR.lock().unwrap().as_ref() {
+        // Replaced by the listener count on the next message
+        set_progress_bar_suffix(progress_bar, "Reconnecting...".to_owned());
+    }
 
-    let message = websocket_stream
-        .next()
-        .await
-        .context("Cannot get message from WebSocket")??;
+    if let Some(player) = PLAYER.lock().unwrap().as_ref() {
+        player.reconnect();
+    }
 
-    let code_radio_message: CodeRadioMessage =
-        serde_json::de::from_str(message.into_text()?.as_str())?;
+    reconnect_websocket_with_retries(websocket_stream).await
+}
 
-    Ok(code_radio_message)
+// For `--min-play-seconds`. Never completes if no song change is pending.
+async fn sleep_until_pending_commit(pending_commit_at: Option<Instant>) {
+    match pending_commit_at {
+        Some(pending_commit_at) => tokio::time::sleep_until(pending_commit_at.into()).await,
+        None => std::future::pending().await,
+    }
 }
 
-// (Call this method when receiving a new message from Code Radio's websocket.)
-// Update progress bar's progress and listeners count suffix.
-// If song id changes, print the new song's info on screen.
-fn update_song_info_on_screen(message: CodeRadioMessage, last_song_id: &mut String) {
-    let song = message.now_playing.song;
+// For `--reconnect-interval`. Never completes without it.
+async fn tick_refresh_timer(refresh_timer: &mut Option<Interval>) {
+    match refresh_timer {
+        Some(timer) => {
+            timer.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
 
-    let elapsed_seconds = message.now_playing.elapsed;
-    let total_seconds = message.now_playing.duration; // Note: This may be 0
+// Cycle only the websocket to keep the session fresh. The audio stream is separate and keeps playing.
+// Not counted as a reconnect in stats unless the first attempt fails.
+async fn refresh_websocket(
+    websocket_stream: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+) -> Result<CodeRadioMessage> {
+    match reconnect_websocket_and_get_next_message(websocket_stream).await {
+        Ok(message) => Ok(message),
+        Err(_) => reconnect_websocket_with_retries(websocket_stream).await,
+    }
+}
 
-    let progress_bar_preffix =
-        get_progress_bar_prefix(PLAYER.lock().unwrap().as_ref().map(Player::volume));
-    let progress_bar_suffix = get_progress_bar_suffix(message.listeners.current);
+// Waits first if the circuit breaker is open, like when the server is down
+async fn connect_websocket() -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+    let circuit_breaker = CIRCUIT_BREAKER.lock().unwrap().clone();
+    if let Some(cooldown) = circuit_breaker.cooldown() {
+        print_above_progress_bar(|| {
+            writeline!(
+                "{}",
+                theme::paint(
+                    &format!(
+                        "Connecting failed {} times in a row. Retrying in {} seconds...",
+                        circuit_breaker.consecutive_failures(),
+                        cooldown.as_secs()
+                    ),
+                    Role::Warning
+                )
+            );
+        });
+        sleep(cooldown).await;
+    }
 
-    let mut progress_bar_guard = PROGRESS_BAR.lock().unwrap();
-    if song.id != *last_song_id {
-        if let Some(progress_bar) = progress_bar_guard.as_ref() {
+    let result = http_client::connect_websocket(http_client::websocket_api_url()).await;
+    CIRCUIT_BREAKER.lock().unwrap().record(&result);
+    result
+}
+
+// For `--follow`. Print the error, then keep connecting with backoff until a message is received.
+async fn connect_websocket_forever(
+    mut error: anyhow::Error,
+    backoff: &mut Backoff,
+) -> (WebSocketStream<MaybeTlsStream<TcpStream>>, CodeRadioMessage) {
+    loop {
+        let delay = backoff.next_delay();
+        print_above_progress_bar(|| {
+            terminal::print_error(format!("{error:#}"));
+            writeline!("Reconnecting in {} seconds...", delay.as_secs());
+        });
+        sleep(delay).await;
+
+        stats::STATS.lock().unwrap().reconnect_count += 1;
+        let mut websocket_stream = match connect_websocket().await {
+            Ok(websocket_stream) => websocket_stream,
+            Err(e) => {
+                error = e;
+                continue;
+            }
+        };
+        match get_first_websocket_message(&mut websocket_stream).await {
+            Ok(message) => return (websocket_stream, message),
+            Err(e) => error = e,
+        }
+    }
+}
+
+async fn reconnect_websocket_and_get_next_message(
+    websocket_stream: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+) -> Result<CodeRadioMessage> {
+    let _ = websocket_stream.close(None).await;
+    *websocket_stream = connect_websocket().await?;
+    get_first_websocket_message(websocket_stream).await
+}
+
+// Unlike `get_next_websocket_message()`, doesn't reconnect on failure
+async fn get_first_websocket_message(
+    websocket_stream: &mut WebSocketStream<MaybeTlsStream<TcpStream>>,
+) -> Result<CodeRadioMessage> {
+    let message = websocket_stream
+        .next()
+        .await
+        .context("Cannot get message from WebSocket")??;
+
+    let message_text = message.into_text()?;
+    message_dump::dump(&message_text);
+    parse_message(&message_text)
+}
+
+// With `--strict`, unknown and missing fields are errors
+fn parse_message(message_text: &str) -> Result<CodeRadioMessage> {
+    if *STRICT_PARSING.lock().unwrap() {
+        schema::validate(message_text)?;
+    }
+    let mut message: CodeRadioMessage = serde_json::from_str(message_text)?;
+    if *SPLIT_TITLE.lock().unwrap() {
+        message.split_titles();
+    }
+    Ok(message)
+}
+
+// (Call this method when receiving a new message from Code Radio's websocket.)
+// Update progress bar's progress and listeners count suffix.
+// If the song changed, print the new song's info on screen.
+// Doesn't touch `PLAYER`, since the audio stream is continuous across songs.
+fn update_song_info_on_screen(
+    message: CodeRadioMessage,
+    song_change: Option<&SongChange>, // `Some` if the song changed
+    display_options: &SongInfoDisplayOptions,
+    display_state: &mut SongInfoDisplayState,
+) {
+    let song = &message.now_playing.song;
+
+    let total_seconds = message.now_playing.duration; // Note: This may be 0
+    let elapsed_seconds = utils::clamp_elapsed_seconds(message.now_playing.elapsed, total_seconds);
+
+    let progress_bar_preffix = get_progress_bar_prefix_from_player(PLAYER.lock().unwrap().as_ref());
+    let listener_count = message.listeners.current;
+    display_state.peak_listener_count = display_state.peak_listener_count.max(listener_count);
+    let unique_listener_count = match display_options.listeners_format {
+        ListenersFormat::Simple => None,
+        ListenersFormat::Detailed => message.listeners.unique,
+    };
+    let progress_bar_suffix = get_progress_bar_suffix(
+        listener_count,
+        unique_listener_count,
+        display_state.last_listener_count,
+        display_options
+            .show_peak_listeners
+            .then_some(display_state.peak_listener_count),
+    );
+    display_state.last_listener_count = Some(listener_count);
+
+    LAST_ELAPSED_SECONDS
+        .lock()
+        .unwrap()
+        .replace((Instant::now(), elapsed_seconds));
+
+    let mut progress_bar_guard = PROGRESS_BAR.lock().unwrap();
+    if let Some(song_change) = song_change.filter(|_| display_options.mono_progress) {
+        if let Some(progress_bar) = progress_bar_guard.as_ref() {
+            progress_bar.finish_and_clear();
+        }
+        let song_text = match display_options.song_template {
+            Some(ref template) => {
+                let station_name = song_change
+                    .station_name
+                    .clone()
+                    .unwrap_or_else(|| get_station_name_from_api_message(&message));
+                template.render(&get_template_values(&message, &station_name))
+            }
+            None => format!("{} - {}", song.artist, song.title),
+        };
+        let progress_bar = create_mono_progress_bar(
+            total_seconds,
+            display_options,
+            song_text,
+            elapsed_seconds,
+            progress_bar_preffix,
+        );
+        set_progress_bar_suffix(&progress_bar, progress_bar_suffix);
+        *progress_bar_guard = Some(progress_bar);
+    } else if let Some(song_change) = song_change {
+        if let Some(progress_bar) = progress_bar_guard.as_ref() {
             progress_bar.finish_and_clear();
         }
 
-        *last_song_id = song.id.clone();
+        let timestamp = display_options.timestamp_format.map(|format| {
+            let now = LocalDateTime::now();
+            let timestamp = match format {
+                TimestampFormat::Time => now.format_time(),
+                TimestampFormat::Iso => now.format_iso8601(),
+            };
+            theme::paint(&format!("[{timestamp}]"), Role::Accent)
+        });
 
         writeline!();
-        writeline!("{}       {}", "Song:".bright_green(), song.title);
-        writeline!("{}     {}", "Artist:".bright_green(), song.artist);
-        writeline!("{}      {}", "Album:".bright_green(), song.album);
+        match display_options.song_template {
+            Some(ref template) => {
+                let station_name = song_change
+                    .station_name
+                    .clone()
+                    .unwrap_or_else(|| get_station_name_from_api_message(&message));
+                let template_values = get_template_values(&message, &station_name);
+                match timestamp {
+                    Some(timestamp) => {
+                        writeline!("{} {}", timestamp, template.render(&template_values));
+                    }
+                    None => writeline!("{}", template.render(&template_values)),
+                }
+            }
+            None => {
+                if let Some(timestamp) = timestamp {
+                    writeline!("{}", timestamp);
+                }
+                display_song_field("Song:", Role::Song, &song.title);
+                display_song_field("Artist:", Role::Artist, &song.artist);
+                display_song_field("Album:", Role::Album, &song.album);
+                display_extra_song_metadata(song);
+            }
+        }
+
+        if let Some(segment) = song.segment() {
+            display_segment(segment);
+        }
+        display_state.last_segment = song.segment().map(str::to_owned);
+
+        if display_options.show_next {
+            let next_song = get_next_song(&message);
+            if let Some(next_song) = next_song {
+                display_next_song(next_song);
+            }
+            display_state.last_next_song_id = next_song.map(|s| s.id.clone());
+        }
 
         let progress_bar_len = if total_seconds > 0 {
             total_seconds as u64
@@ -254,40 +1419,224 @@ fn update_song_info_on_screen(message: CodeRadioMessage, last_song_id: &mut Stri
             u64::MAX
         };
 
-        let progress_bar_style =
-            ProgressStyle::with_template("{prefix}  {wide_bar} {progress_info} - {msg}")
-                .unwrap()
-                .with_key(
-                    "progress_info",
-                    |state: &ProgressState, write: &mut dyn Write| {
-                        let progress_info =
-                            get_progress_bar_progress_info(state.pos(), state.len());
-                        write!(write, "{progress_info}").unwrap();
-                    },
-                );
-
-        let progress_bar = ProgressBar::new(progress_bar_len)
-            .with_style(progress_bar_style)
-            .with_position(elapsed_seconds as u64)
-            .with_prefix(progress_bar_preffix)
-            .with_message(progress_bar_suffix);
+        let progress_bar_style = ProgressStyle::with_template(
+            "{prefix}  {wide_bar} {progress_info} - {msg}{buffer_status}",
+        )
+        .unwrap()
+        .progress_chars(&display_options.progress_chars)
+        .with_key(
+            "progress_info",
+            |state: &ProgressState, write: &mut dyn Write| {
+                let progress_info = get_progress_bar_progress_info(state.pos(), state.len());
+                write!(write, "{progress_info}").unwrap();
+            },
+        )
+        .with_key("buffer_status", write_buffer_status);
+
+        let progress_bar = ProgressBar::with_draw_target(
+            Some(progress_bar_len),
+            display_options
+                .progress_mode
+                .draw_target(display_options.refresh_interval),
+        )
+        .with_style(progress_bar_style)
+        .with_position(elapsed_seconds)
+        .with_prefix(progress_bar_preffix)
+        .with_message(progress_bar_suffix);
 
         progress_bar.tick();
 
         *progress_bar_guard = Some(progress_bar);
     } else if let Some(progress_bar) = progress_bar_guard.as_ref() {
-        progress_bar.set_position(elapsed_seconds as u64);
-        progress_bar.set_message(progress_bar_suffix);
+        // The next song may change while the current song is playing.
+        // Not shown with `--mono-progress`, which keeps everything on one line.
+        if display_options.show_next && !display_options.mono_progress {
+            let next_song = get_next_song(&message);
+            let next_song_id = next_song.map(|s| s.id.clone());
+            if next_song_id != display_state.last_next_song_id {
+                if let Some(next_song) = next_song {
+                    progress_bar.suspend(|| display_next_song(next_song));
+                }
+                display_state.last_next_song_id = next_song_id;
+            }
+        }
+
+        // Segments of a long show change while the song stays the same
+        let segment = song.segment();
+        if segment != display_state.last_segment.as_deref() && !display_options.mono_progress {
+            if let Some(segment) = segment {
+                progress_bar.suspend(|| display_segment(segment));
+            }
+            display_state.last_segment = segment.map(str::to_owned);
+        }
+
+        let position = reconcile_progress_bar_position(
+            progress_bar.position(),
+            elapsed_seconds,
+            progress_bar.length(),
+        );
+        update_progress_bar(progress_bar, Some(position), None, None);
+        set_progress_bar_suffix(progress_bar, progress_bar_suffix);
+    }
+}
+
+// For `--mono-progress`. Like "♪ Artist - Title [01:14 / 05:14] ▓▓▓░░ Volume 5/9 · Listeners: 412 –".
+// The song is truncated to fit the terminal width.
+fn create_mono_progress_bar(
+    total_seconds: i64,
+    display_options: &SongInfoDisplayOptions,
+    song_text: String,
+    elapsed_seconds: u64,
+    prefix: String,
+) -> ProgressBar {
+    let progress_bar_len = if total_seconds > 0 {
+        total_seconds as u64
+    } else {
+        u64::MAX
+    };
+
+    let progress_bar_style = ProgressStyle::with_template(
+        "♪ {wide_msg} [{progress_info}] {bar:10} {prefix} · {suffix}{buffer_status}",
+    )
+    .unwrap()
+    .progress_chars(&display_options.progress_chars)
+    .with_key(
+        "progress_info",
+        |state: &ProgressState, write: &mut dyn Write| {
+            let progress_info = get_progress_bar_progress_info(state.pos(), state.len());
+            write!(write, "{progress_info}").unwrap();
+        },
+    )
+    .with_key("suffix", |_: &ProgressState, write: &mut dyn Write| {
+        let suffix = MONO_PROGRESS_SUFFIX.lock().unwrap();
+        write!(write, "{}", suffix.as_deref().unwrap_or_default()).unwrap();
+    })
+    .with_key("buffer_status", write_buffer_status);
+
+    let progress_bar = ProgressBar::with_draw_target(
+        Some(progress_bar_len),
+        display_options
+            .progress_mode
+            .draw_target(display_options.refresh_interval),
+    )
+    .with_style(progress_bar_style)
+    .with_position(elapsed_seconds)
+    .with_prefix(prefix)
+    .with_message(song_text);
+
+    progress_bar.tick();
+    progress_bar
+}
+
+// The listener count, or a status like "Reconnecting...". It's the progress bar message,
+// except with `--mono-progress` where the message is the song.
+fn set_progress_bar_suffix(progress_bar: &ProgressBar, suffix: String) {
+    let mut mono_progress_suffix = MONO_PROGRESS_SUFFIX.lock().unwrap();
+    match mono_progress_suffix.as_mut() {
+        Some(mono_progress_suffix_value) => {
+            if *mono_progress_suffix_value != suffix {
+                *mono_progress_suffix_value = suffix;
+                // Release the lock before repainting, which reads it
+                drop(mono_progress_suffix);
+                progress_bar.tick();
+            }
+        }
+        None => update_progress_bar(progress_bar, None, None, Some(suffix)),
+    }
+}
+
+// Setting a progress bar's value repaints it even if the value is the same. Only set changed values.
+fn update_progress_bar(
+    progress_bar: &ProgressBar,
+    position: Option<u64>,
+    prefix: Option<String>,
+    message: Option<String>,
+) {
+    if let Some(position) = position.filter(|p| *p != progress_bar.position()) {
+        progress_bar.set_position(position);
+    }
+    if let Some(prefix) = prefix.filter(|p| *p != progress_bar.prefix()) {
+        progress_bar.set_prefix(prefix);
+    }
+    if let Some(message) = message.filter(|m| *m != progress_bar.message()) {
+        progress_bar.set_message(message);
+    }
+}
+
+// Like "Up next:    Chill Beats - Morning Coffee"
+fn display_next_song(next_song: &Song) {
+    display_song_field(
+        "Up next:",
+        Role::Accent,
+        &format!("{} - {}", next_song.artist, next_song.title),
+    );
+}
+
+fn display_segment(segment: &str) {
+    display_song_field("Segment:", Role::Accent, segment);
+}
+
+// Like "Album:      Some Album". Long values are wrapped to the terminal width,
+// with continuation lines aligned under the value.
+fn display_song_field(label: &str, role: Role, value: &str) {
+    let label_padding = " ".repeat(SONG_FIELD_VALUE_COLUMN.saturating_sub(label.len()));
+    let lines = match terminal::STDOUT.size_checked() {
+        Some((_rows, columns)) if columns as usize > SONG_FIELD_VALUE_COLUMN => {
+            utils::wrap_text(value, columns as usize - SONG_FIELD_VALUE_COLUMN)
+        }
+        _ => vec![value.to_owned()],
+    };
+
+    let mut lines = lines.into_iter();
+    writeline!(
+        "{}{label_padding}{}",
+        theme::paint(label, role),
+        lines.next().unwrap_or_default()
+    );
+    for line in lines {
+        writeline!("{}{line}", " ".repeat(SONG_FIELD_VALUE_COLUMN));
     }
 }
 
-fn get_progress_bar_prefix(volume: Option<u8>) -> String {
+// Only print the metadata fields that are provided by the API
+fn display_extra_song_metadata(song: &Song) {
+    for (label, value) in get_extra_song_metadata(song) {
+        display_song_field(label, Role::Accent, value);
+    }
+}
+
+// Print something without messing up the progress bar
+fn print_above_progress_bar(print: impl FnOnce()) {
+    match PROGRESS_BAR.lock().unwrap().as_ref() {
+        Some(progress_bar) => progress_bar.suspend(print),
+        None => print(),
+    }
+}
+
+// "Volume 9/9", "Volume 9/9 (paused)" or "Volume 9/9 (buffering...)". Paused wins over buffering.
+fn get_progress_bar_prefix(volume: Option<u8>, buffering: bool, paused: bool) -> String {
     let volume_char = volume.map_or_else(|| "*".to_owned(), |v| v.to_string());
-    format!("Volume {volume_char}/9")
+    if paused {
+        format!(
+            "Volume {volume_char}/9 {}",
+            theme::paint("(paused)", Role::Warning)
+        )
+    } else if buffering {
+        format!(
+            "Volume {volume_char}/9 {}",
+            theme::paint("(buffering...)", Role::Warning)
+        )
+    } else {
+        format!("Volume {volume_char}/9")
+    }
 }
 
-fn get_progress_bar_suffix(listener_count: i64) -> String {
-    format!("Listeners: {listener_count}")
+fn get_progress_bar_prefix_from_player(player: Option<&Player>) -> String {
+    get_progress_bar_prefix(
+        player.map(Player::volume),
+        player.is_some_and(Player::is_buffering),
+        player.is_some_and(Player::is_paused),
+    )
 }
 
 // If elapsed seconds and total seconds are both known:
@@ -309,73 +1658,1012 @@ fn get_progress_bar_progress_info(elapsed_seconds: u64, total_seconds: Option<u6
     humanized_elapsed_duration
 }
 
+// For `--buffer-status`. Like " · buf:85%" after the progress bar message. Reads only `BUFFER_STATUS`,
+// since it's called while repainting.
+fn write_buffer_status(_: &ProgressState, write: &mut dyn Write) {
+    if let Some(ref buffer_status) = *BUFFER_STATUS.lock().unwrap() {
+        write!(write, "{buffer_status}").unwrap();
+    }
+}
+
+fn format_buffer_status(buffer_fill: Option<f32>) -> String {
+    let Some(buffer_fill) = buffer_fill else {
+        return String::new(); // Not connected yet
+    };
+    let status = format!("buf:{:.0}%", buffer_fill * 100.0);
+    if buffer_fill < BUFFER_STATUS_LOW_FILL {
+        format!(" · {}", theme::paint(&status, Role::Warning))
+    } else {
+        format!(" · {status}")
+    }
+}
+
+// The position is estimated from the last elapsed seconds reported by the API and the time since then,
+// so that it doesn't drift from the API
 async fn tick_progress_bar() {
     let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+        let (progress_bar_prefix, buffer_fill) = {
+            let player = PLAYER.lock().unwrap();
+            (
+                get_progress_bar_prefix_from_player(player.as_ref()),
+                player.as_ref().and_then(Player::buffer_fill),
+            )
+        };
+        if let Some(ref mut buffer_status) = *BUFFER_STATUS.lock().unwrap() {
+            *buffer_status = format_buffer_status(buffer_fill);
+        }
+        let estimated_elapsed_seconds =
+            LAST_ELAPSED_SECONDS
+                .lock()
+                .unwrap()
+                .map(|(reported_at, elapsed_seconds)| {
+                    elapsed_seconds + reported_at.elapsed().as_secs()
+                });
+        if let Some(progress_bar) = PROGRESS_BAR.lock().unwrap().as_ref() {
+            let position = estimated_elapsed_seconds.map(|estimated_elapsed_seconds| {
+                reconcile_progress_bar_position(
+                    progress_bar.position(),
+                    estimated_elapsed_seconds,
+                    progress_bar.length(),
+                )
+            });
+            // The prefix shows or hides the buffering indicator
+            update_progress_bar(progress_bar, position, Some(progress_bar_prefix), None);
+        }
+    }
+}
+
+// Replaces the animated progress bar when it's not shown, like in CI logs.
+// Prints the progress bar's content without the bar.
+async fn print_progress_status_periodically() {
+    let mut interval = tokio::time::interval(Duration::from_secs(30));
+    interval.tick().await; // The first tick completes immediately
     loop {
         interval.tick().await;
         if let Some(progress_bar) = PROGRESS_BAR.lock().unwrap().as_ref() {
-            progress_bar.inc(1);
+            let progress_info =
+                get_progress_bar_progress_info(progress_bar.position(), progress_bar.length());
+            writeline!(
+                "{}  {} - {}",
+                progress_bar.prefix(),
+                progress_info,
+                progress_bar.message()
+            );
         }
     }
 }
 
-fn handle_keyboard_events() -> ! {
+// Move the progress bar to the new position without jumping backward a little, and without overshooting the total
+fn reconcile_progress_bar_position(
+    current_position: u64,
+    new_position: u64,
+    total: Option<u64>,
+) -> u64 {
+    let position = if current_position > new_position
+        && current_position - new_position <= PROGRESS_BAR_MAX_PAUSED_SECONDS
+    {
+        current_position
+    } else {
+        new_position
+    };
+    total.map_or(position, |total| position.min(total))
+}
+
+fn ring_bell(bell: Bell) {
+    match bell {
+        Bell::Terminal => print_above_progress_bar(terminal::ring_bell),
+        Bell::Chime => {
+            if let Some(player) = PLAYER.lock().unwrap().as_ref() {
+                player.play_chime();
+            }
+        }
+    }
+}
+
+// Uses the keys in the keybindings config
+fn get_key_help(toggle_mute: bool) -> String {
+    let keys = CONFIG.lock().unwrap().keybindings.clone();
+    let volume_hint = if toggle_mute {
+        "Press 1-9 to adjust volume, 0 to toggle mute."
+    } else {
+        "Press 0-9 to adjust volume."
+    };
+
+    format!(
+        "{volume_hint} Press {} / {} to turn volume up / down, {} to toggle mute.
+Press {} to pause / resume, {} to jump to live, {} to show stats, {} to reconnect, {} to show this help. Press {} or Ctrl+C to exit.
+Press {} to add/remove current station from favorites, {} to play next favorite, {} to pick a favorite.
+Press {} to play next station, {} to copy the current song.",
+        keys.volume_up,
+        keys.volume_down,
+        keys.mute,
+        key_name(keys.toggle_pause),
+        key_name(keys.seek_to_live),
+        keys.stats,
+        keys.reconnect,
+        keys.help,
+        keys.quit,
+        keys.toggle_favorite,
+        keys.next_favorite,
+        keys.pick_favorite,
+        keys.next_station,
+        keys.copy
+    )
+}
+
+// Like "space" for ' '
+fn key_name(key: char) -> String {
+    match key {
+        ' ' => "space".to_owned(),
+        key => key.to_string(),
+    }
+}
+
+// If `toggle_mute` is true, pressing 0 mutes, and pressing 0 again restores the volume before muting
+fn handle_keyboard_events(
+    toggle_mute: bool,
+    key_lookup_table: HashMap<char, Action>,
+    reconnect_request_sender: tokio::sync::mpsc::UnboundedSender<()>,
+) -> ! {
+    let mut volume_before_mute: Option<u8> = None;
+
     loop {
-        if let Some(n) = terminal::read_char().ok().and_then(|c| c.to_digit(10)) {
+        let Ok(c) = terminal::read_char() else {
+            continue;
+        };
+
+        if let Some(action) = key_lookup_table.get(&c) {
+            match action {
+                Action::Stats => print_stats(),
+                Action::Reconnect => {
+                    let _ = reconnect_request_sender.send(());
+                }
+                Action::SeekToLive => jump_to_live(),
+                Action::ToggleFavorite => toggle_current_station_favorite(),
+                Action::NextFavorite => play_next_favorite_station(),
+                Action::PickFavorite => pick_favorite_station(),
+                Action::NextStation => play_next_station(),
+                Action::Copy => copy_current_song(),
+                Action::VolumeUp | Action::VolumeDown => {
+                    if let Some(player) = PLAYER.lock().unwrap().as_mut() {
+                        let volume = match action {
+                            Action::VolumeUp => player.volume().saturating_add(1),
+                            _ => player.volume().saturating_sub(1),
+                        };
+                        volume_before_mute = None;
+                        set_player_volume(player, volume);
+                    }
+                }
+                Action::TogglePause => {
+                    if let Some(player) = PLAYER.lock().unwrap().as_mut() {
+                        set_player_paused(player, !player.is_paused());
+                    }
+                }
+                Action::Mute => {
+                    if let Some(player) = PLAYER.lock().unwrap().as_mut() {
+                        let volume = match volume_before_mute.take() {
+                            Some(previous_volume) if player.volume() == 0 => previous_volume,
+                            _ => {
+                                volume_before_mute = Some(player.volume());
+                                0
+                            }
+                        };
+                        set_player_volume(player, volume);
+                    }
+                }
+                Action::Quit => {
+                    if let Some(progress_bar) = PROGRESS_BAR.lock().unwrap().as_ref() {
+                        progress_bar.finish_and_clear();
+                    }
+                    print_stats_on_exit();
+                    // `terminal::read_char()` has returned, so the terminal is not in raw mode now
+                    ExitCode::Success.exit();
+                }
+                Action::Help => {
+                    let key_help = get_key_help(toggle_mute);
+                    print_above_progress_bar(|| writeline!("{key_help}"));
+                }
+            }
+        } else if let Some(n) = c.to_digit(10) {
             if let Some(player) = PLAYER.lock().unwrap().as_mut() {
-                let volume = n as u8;
-                if player.volume() == volume {
+                let mut volume = n as u8;
+                if toggle_mute && volume == 0 {
+                    match volume_before_mute.take() {
+                        Some(previous_volume) if player.volume() == 0 => volume = previous_volume,
+                        _ => volume_before_mute = Some(player.volume()),
+                    }
+                } else {
+                    volume_before_mute = None;
+                }
+
+                set_player_volume(player, volume);
+            }
+        }
+    }
+}
+
+fn print_stats() {
+    let summary = stats::STATS.lock().unwrap().summary();
+    print_above_progress_bar(|| writeline!("{}", summary.bright_black()));
+}
+
+async fn print_stats_periodically() {
+    let mut interval = tokio::time::interval(Duration::from_secs(10 * 60));
+    interval.tick().await; // The first tick completes immediately
+    loop {
+        interval.tick().await;
+        print_stats();
+    }
+}
+
+// For `--print-stats-on-exit`. The progress bar should be cleared already.
+// Not printed on Ctrl+C, which kills the process.
+fn print_stats_on_exit() {
+    if *PRINT_STATS_ON_EXIT.lock().unwrap() {
+        writeline!("{}", stats::STATS.lock().unwrap().listening.summary());
+    }
+}
+
+// Credit played time to the current station and artist. Paused and buffering time doesn't count.
+async fn track_listening_time() {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    let mut last_tick = Instant::now();
+    loop {
+        interval.tick().await;
+        let elapsed = last_tick.elapsed();
+        last_tick = Instant::now();
+
+        let is_playing = PLAYER
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|player| !player.is_paused() && !player.is_buffering());
+        if !is_playing {
+            continue;
+        }
+
+        let artist = LAST_MESSAGE
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|m| m.now_playing.song.artist.clone())
+            .unwrap_or_default();
+        stats::STATS.lock().unwrap().listening.add_time(
+            CURRENT_STATION.lock().unwrap().as_ref(),
+            &artist,
+            elapsed,
+        );
+    }
+}
+
+fn toggle_current_station_favorite() {
+    let Some(station) = CURRENT_STATION.lock().unwrap().clone() else {
+        return;
+    };
+
+    let mut config = CONFIG.lock().unwrap();
+    let is_favorite = config.toggle_favorite(station.id);
+    let save_result = config.save();
+    drop(config);
+
+    print_above_progress_bar(|| {
+        if is_favorite {
+            writeline!("Added \"{}\" to favorites", station.name);
+        } else {
+            writeline!("Removed \"{}\" from favorites", station.name);
+        }
+        if let Err(e) = save_result {
+            terminal::print_error(format!("{e:#}"));
+        }
+    });
+}
+
+// Favorite stations in the order of the station list
+fn get_favorite_stations() -> Vec<Remote> {
+    let config = CONFIG.lock().unwrap();
+    STATIONS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|s| config.is_favorite(s.id))
+        .cloned()
+        .collect()
+}
+
+fn play_next_favorite_station() {
+    let favorite_stations = get_favorite_stations();
+    if favorite_stations.is_empty() {
+        print_above_progress_bar(|| writeline!("No favorite stations. Press f to add one."));
+        return;
+    }
+
+    play_station_after_current(&favorite_stations);
+}
+
+fn play_next_station() {
+    let stations = STATIONS.lock().unwrap().clone();
+    if !stations.is_empty() {
+        play_station_after_current(&stations);
+    }
+}
+
+// The first station if the current one is not in `stations`
+fn play_station_after_current(stations: &[Remote]) {
+    let current_station_id = CURRENT_STATION.lock().unwrap().as_ref().map(|s| s.id);
+    let next_station = match stations
+        .iter()
+        .position(|s| Some(s.id) == current_station_id)
+    {
+        Some(i) => &stations[(i + 1) % stations.len()],
+        None => &stations[0],
+    };
+
+    if Some(next_station.id) != current_station_id {
+        switch_station(next_station);
+    }
+}
+
+// Like "Lofi Artist - Night Drive"
+fn copy_current_song() {
+    let Some(song) = LAST_MESSAGE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|m| m.now_playing.song.clone())
+    else {
+        return;
+    };
+
+    let text = format!("{} - {}", song.artist, song.title);
+    let feedback = if terminal::copy_to_clipboard(&text) {
+        format!("Copied \"{text}\"")
+    } else {
+        "Cannot copy without a terminal".to_owned()
+    };
+    print_above_progress_bar(|| writeline!("{}", feedback.bright_black()));
+}
+
+fn pick_favorite_station() {
+    let favorite_stations = get_favorite_stations();
+    if favorite_stations.is_empty() {
+        print_above_progress_bar(|| writeline!("No favorite stations. Press f to add one."));
+        return;
+    }
+
+    // Don't lock `PROGRESS_BAR` while the prompt is shown, otherwise the main loop will be blocked
+    let progress_bar = PROGRESS_BAR.lock().unwrap().clone();
+    let prompt =
+        || get_station_select("Select a favorite station:", &favorite_stations).raw_prompt();
+    let selected_option = match progress_bar {
+        Some(progress_bar) => progress_bar.suspend(prompt),
+        None => prompt(),
+    };
+
+    if let Ok(selected_option) = selected_option {
+        switch_station(&favorite_stations[selected_option.index]);
+    }
+}
+
+// Type to filter stations fuzzily. Use `raw_prompt()` to get the index of the selected station,
+// which is its index in `stations` even after filtering.
+fn get_station_select<'a>(message: &'a str, stations: &[Remote]) -> Select<'a, String> {
+    let station_descriptions = stations.iter().map(Remote::description).collect();
+    Select::new(message, station_descriptions)
+        .with_page_size(8)
+        .with_filter(&|filter, _, station_description, _| {
+            utils::fuzzy_matches(filter, station_description)
+        })
+}
+
+fn switch_station(station: &Remote) {
+    // Set `LISTEN_URL` before locking `PLAYER`. See `recover_player()`.
+    LISTEN_URL.lock().unwrap().replace(station.url.clone());
+    CURRENT_STATION.lock().unwrap().replace(station.clone());
+    if let Some(player) = PLAYER.lock().unwrap().as_ref() {
+        player.play(&station.url);
+    }
+
+    let save_result = remember_last_station(station);
+
+    print_above_progress_bar(|| {
+        writeline!(
+            "{}    {}",
+            theme::paint("Station:", Role::Station),
+            station.name
+        );
+        if let Err(e) = save_result {
+            terminal::print_error(format!("{e:#}"));
+        }
+    });
+}
+
+// Like "Preferred station 2 of 3". `None` if the station is not preferred.
+fn describe_preference_level(station: &Remote) -> Option<String> {
+    let config = CONFIG.lock().unwrap();
+    let level = preferred_stations::level_of(&config.preferred_stations, station)?;
+    Some(format!(
+        "Preferred station {level} of {}",
+        config.preferred_stations.len()
+    ))
+}
+
+// Check that the stream opens. The response is dropped after receiving the headers.
+async fn probe_stream(http_client: &reqwest::Client, url: &str) -> Result<()> {
+    http_client
+        .get(url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)?;
+    Ok(())
+}
+
+// Like `probe_stream()`, and check that the start of the stream has MP3 frames.
+// Other formats like AAC are not supported.
+async fn probe_mp3_stream(http_client: &reqwest::Client, url: &str) -> Result<()> {
+    let cannot_open = || format!("Cannot open stream \"{url}\"");
+    let mut response = http_client
+        .get(url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .with_context(cannot_open)?;
+
+    let mut start = Vec::new();
+    while !mp3_stream_decoder::contains_mp3_frame(&start) {
+        match response.chunk().await.with_context(cannot_open)? {
+            Some(chunk) if start.len() < mp3_stream_decoder::MAX_SNIFF_LEN => {
+                start.extend_from_slice(&chunk);
+            }
+            _ => {
+                let content_type = response
+                    .headers()
+                    .get(reqwest::header::CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or("unknown");
+                return Err(anyhow!(
+                    "Stream \"{url}\" is not MP3 (Content-Type \"{content_type}\"). Only MP3 streams are supported."
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// For `preferred_stations` in the config. When the stream fails, play the most preferred reachable station.
+// While playing a less preferred one, switch back to a more preferred one when it's reachable again.
+async fn watch_preferred_stations(http_client: reqwest::Client) {
+    let mut interval = tokio::time::interval(PREFERRED_STATIONS_CHECK_INTERVAL);
+    let mut last_recovery_check = Instant::now();
+    loop {
+        interval.tick().await;
+
+        let stream_failed = PLAYER
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(Player::has_stream_failed);
+        let preferences = CONFIG.lock().unwrap().preferred_stations.clone();
+        let candidates = preferred_stations::find(&preferences, &STATIONS.lock().unwrap());
+        let Some(current_station) = CURRENT_STATION.lock().unwrap().clone() else {
+            continue;
+        };
+
+        if stream_failed {
+            // Walk the list from the top. If none is reachable, the player keeps retrying the current stream.
+            for candidate in candidates {
+                if probe_stream(&http_client, &candidate.station.url)
+                    .await
+                    .is_err()
+                {
                     continue;
                 }
-                player.set_volume(volume);
-                if let Some(progress_bar) = PROGRESS_BAR.lock().unwrap().as_mut() {
-                    progress_bar.set_prefix(get_progress_bar_prefix(Some(volume)));
-                };
+                if candidate.station.id != current_station.id {
+                    print_above_progress_bar(|| {
+                        writeline!(
+                            "{}",
+                            theme::paint(
+                                &format!(
+                                    "Stream failed, switching to preferred station {} of {}",
+                                    candidate.level,
+                                    preferences.len()
+                                ),
+                                Role::Warning
+                            )
+                        );
+                    });
+                    switch_station(&candidate.station);
+                }
+                break;
+            }
+        } else if last_recovery_check.elapsed() >= PREFERRED_STATIONS_RECOVERY_CHECK_INTERVAL {
+            last_recovery_check = Instant::now();
+            // Only while playing a preferred station, so that a station switched to manually is kept
+            let Some(current_level) = preferred_stations::level_of(&preferences, &current_station)
+            else {
+                continue;
+            };
+            for candidate in candidates.into_iter().filter(|c| c.level < current_level) {
+                if probe_stream(&http_client, &candidate.station.url)
+                    .await
+                    .is_ok()
+                {
+                    print_above_progress_bar(|| {
+                        writeline!(
+                            "{}",
+                            format!(
+                                "Preferred station {} of {} is reachable again, switching back",
+                                candidate.level,
+                                preferences.len()
+                            )
+                            .bright_black()
+                        );
+                    });
+                    switch_station(&candidate.station);
+                    break;
+                }
             }
         }
     }
 }
 
-async fn select_station() -> Result<Remote> {
+// Save the station to the config file for `--resume`
+fn remember_last_station(station: &Remote) -> Result<()> {
+    let mut config = CONFIG.lock().unwrap();
+    if config.set_last_station(station.id) {
+        config.save()?;
+    }
+    Ok(())
+}
+
+// When stdin is not a terminal, read line commands from it. See `command::Command`.
+fn handle_stdin_commands() {
+    for line in std::io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match line.parse::<Command>() {
+            Ok(Command::Volume(volume)) => {
+                if let Some(player) = PLAYER.lock().unwrap().as_mut() {
+                    set_player_volume(player, volume);
+                }
+            }
+            Ok(command @ (Command::Pause | Command::Resume)) => {
+                if let Some(player) = PLAYER.lock().unwrap().as_mut() {
+                    set_player_paused(player, command == Command::Pause);
+                }
+            }
+            Ok(Command::Live) => jump_to_live(),
+            Ok(Command::Quit) => {
+                if let Some(progress_bar) = PROGRESS_BAR.lock().unwrap().as_ref() {
+                    progress_bar.finish_and_clear();
+                }
+                print_stats_on_exit();
+                ExitCode::Success.exit();
+            }
+            Err(e) => print_above_progress_bar(|| terminal::print_error(e)),
+        }
+    }
+}
+
+// Reconnect only the audio stream, dropping audio buffered by `--buffer-ahead-seconds` or while paused,
+// so that it plays from the live position again. Song info is always live, so only the progress bar is resynced.
+fn jump_to_live() {
+    let mut player_guard = PLAYER.lock().unwrap();
+    let Some(player) = player_guard.as_mut() else {
+        return;
+    };
+    print_above_progress_bar(|| writeline!("Jumping to live..."));
+    if player.is_paused() {
+        // Resuming reconnects
+        set_player_paused(player, false);
+    } else {
+        player.reconnect();
+    }
+    drop(player_guard);
+
+    let estimated_elapsed_seconds = LAST_ELAPSED_SECONDS
+        .lock()
+        .unwrap()
+        .map(|(reported_at, elapsed_seconds)| elapsed_seconds + reported_at.elapsed().as_secs());
+    if let (Some(progress_bar), Some(elapsed_seconds)) = (
+        PROGRESS_BAR.lock().unwrap().as_ref(),
+        estimated_elapsed_seconds,
+    ) {
+        let position = progress_bar
+            .length()
+            .map_or(elapsed_seconds, |total| elapsed_seconds.min(total));
+        update_progress_bar(progress_bar, Some(position), None, None);
+    }
+}
+
+fn set_player_paused(player: &mut Player, paused: bool) {
+    if player.is_paused() == paused {
+        return;
+    }
+    player.set_paused(paused);
+    if let Some(progress_bar) = PROGRESS_BAR.lock().unwrap().as_mut() {
+        progress_bar.set_prefix(get_progress_bar_prefix_from_player(Some(player)));
+    };
+}
+
+fn set_player_volume(player: &mut Player, volume: u8) {
+    if player.volume() == volume {
+        return;
+    }
+    player.set_volume(volume);
+    if let Some(progress_bar) = PROGRESS_BAR.lock().unwrap().as_mut() {
+        progress_bar.set_prefix(get_progress_bar_prefix_from_player(Some(player)));
+    };
+}
+
+// `None` if nothing is selected before `--select-timeout`, then the default station is played
+async fn select_station(
+    http_client: &reqwest::Client,
+    args: &Args,
+    station_filter: &StationFilter,
+) -> Result<Option<Remote>> {
+    let cached_stations = if args.refresh_stations {
+        None
+    } else {
+        station_cache::try_read_stations_from_cache_file(http_client::rest_api_url()).await
+    };
+
+    let stations = match cached_stations {
+        Some(stations) => {
+            // Refresh the cache in background for next time
+            tokio::spawn(get_stations_from_rest_api_and_write_to_cache_file(
+                http_client.clone(),
+            ));
+            stations
+        }
+        None => fetch_stations_with_retries(http_client, args.rest_attempts).await?,
+    };
+    let stations = station_filter.apply(stations);
+    if stations.is_empty() {
+        return Err(anyhow!(NO_MATCHING_STATION_ERROR)).exit_code(ExitCode::BadArguments);
+    }
+
+    let selected_option = match args.select_timeout {
+        #[cfg(unix)]
+        Some(seconds) => {
+            let prompt_stations = stations.clone();
+            let prompt =
+                move || get_station_select("Select a station:", &prompt_stations).raw_prompt();
+            match terminal::prompt_with_timeout(prompt, Duration::from_secs(seconds)).await {
+                Some(answer) => answer,
+                None => {
+                    let station = get_default_station(http_client, &stations, station_filter).await;
+                    let message = format!(
+                        "No station selected in {seconds} seconds, playing \"{}\"",
+                        station.description()
+                    );
+                    writeline!();
+                    writeline!("{}", theme::paint(&message, Role::Warning));
+                    writeline!();
+                    return Ok(Some(station));
+                }
+            }
+        }
+        // Rejected on startup
+        #[cfg(not(unix))]
+        Some(_) => unreachable!(),
+        None => get_station_select("Select a station:", &stations).raw_prompt(),
+    };
+    let selected_option = match selected_option {
+        Ok(selected_option) => selected_option,
+        Err(InquireError::OperationCanceled | InquireError::OperationInterrupted) => {
+            // Pressing Esc or Ctrl+C is not an error.
+            // Exit directly. The terminal is never put into raw mode before playing, so there's nothing to clean up.
+            ExitCode::Success.exit()
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let selected_station = stations[selected_option.index].clone();
+
+    writeline!();
+
+    Ok(Some(selected_station))
+}
+
+// For `--select-timeout`. The station the API plays by default, or the first of `stations` if it's filtered out
+// or the API is not reachable.
+#[cfg(unix)]
+async fn get_default_station(
+    http_client: &reqwest::Client,
+    stations: &[Remote],
+    station_filter: &StationFilter,
+) -> Remote {
+    let default_url = get_message_from_rest_api(http_client)
+        .await
+        .map(|message| message.station.listen_url)
+        .unwrap_or_default();
+    let default_url = station_filter
+        .default_station_url(stations, &default_url)
+        .unwrap_or_default();
+    stations
+        .iter()
+        .find(|s| s.url == default_url)
+        .unwrap_or(&stations[0])
+        .clone()
+}
+
+// Try at most `attempts` times, showing the attempts on a spinner
+async fn fetch_stations_with_retries(
+    http_client: &reqwest::Client,
+    attempts: u32,
+) -> Result<Vec<Remote>> {
     let loading_spinner = ProgressBar::new_spinner()
         .with_style(ProgressStyle::with_template("{spinner} {msg}")?)
-        .with_message("Connecting...");
+        .with_message("Fetching stations...");
     loading_spinner.enable_steady_tick(Duration::from_millis(120));
 
-    let stations = get_stations_from_rest_api().await?;
+    let stations = utils::retry(
+        attempts,
+        CONNECT_RETRY_DELAY,
+        |attempt| {
+            loading_spinner.set_message(format!(
+                "Fetching stations (attempt {attempt}/{attempts})..."
+            ));
+        },
+        || get_stations_from_rest_api_and_write_to_cache_file(http_client.clone()),
+    )
+    .await;
 
     loading_spinner.finish_and_clear();
+    stations
+}
 
-    let station_names: Vec<&str> = stations.iter().map(|s| s.name.as_str()).collect();
+async fn get_message_from_rest_api(http_client: &reqwest::Client) -> Result<CodeRadioMessage> {
+    let message = http_client
+        .get(http_client::rest_api_url())
+        .headers(http_client::api_headers())
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    parse_message(&message)
+}
 
-    let selected_station_name = Select::new("Select a station:", station_names)
-        .with_page_size(8)
-        .prompt()?;
-    let selected_station = stations
-        .iter()
-        .find(|s| s.name == selected_station_name)
-        .unwrap()
-        .clone();
+async fn get_stations_from_rest_api(http_client: &reqwest::Client) -> Result<Vec<Remote>> {
+    let message = get_message_from_rest_api(http_client).await?;
+    let stations = message.get_stations();
+    Ok(stations)
+}
 
-    writeline!();
+// Takes an owned `reqwest::Client` (which is cheap to clone) so that it can be spawned in background
+async fn get_stations_from_rest_api_and_write_to_cache_file(
+    http_client: reqwest::Client,
+) -> Result<Vec<Remote>> {
+    let stations = get_stations_from_rest_api(&http_client).await?;
+    let _ =
+        station_cache::write_stations_to_cache_file(http_client::rest_api_url(), &stations).await;
+    Ok(stations)
+}
 
-    Ok(selected_station)
+// Unless the version is dismissed with `--dismiss-update`
+fn notify_new_release(new_release: &Release) {
+    if CONFIG.lock().unwrap().dismissed_release_version.as_ref() == Some(&new_release.version) {
+        return;
+    }
+
+    writeline!(
+        "{}",
+        theme::paint(
+            &format!("New version available: {}", new_release.version),
+            Role::Warning
+        )
+    );
+    writeline!("{}", theme::paint(&new_release.url, Role::Warning));
+    writeline!(
+        "{}",
+        theme::paint(
+            "Run with --dismiss-update to hide this until the next version",
+            Role::Warning
+        )
+    );
+    writeline!();
 }
 
-async fn get_stations_from_rest_api() -> Result<Vec<Remote>> {
-    let message: CodeRadioMessage = reqwest::get(REST_API_URL).await?.json().await?;
-    let stations = get_stations_from_api_message(&message);
-    Ok(stations)
+// For `--start-paused`
+fn print_start_paused_hint() {
+    let pause_key = key_name(CONFIG.lock().unwrap().keybindings.toggle_pause);
+    writeline!(
+        "{}",
+        theme::paint(
+            &format!("Paused. Press {pause_key} to start playing."),
+            Role::Warning
+        )
+    );
 }
 
-fn get_stations_from_api_message(message: &CodeRadioMessage) -> Vec<Remote> {
-    let mut stations: Vec<Remote> = Vec::new();
-    for remote in &message.station.remotes {
-        stations.push(remote.clone());
+// Like "+ New station: Code Radio (128 kbps MP3)". Stations are matched by listen url.
+fn print_station_changes(previous_stations: &[Remote], stations: &[Remote]) {
+    let added = stations
+        .iter()
+        .filter(|s| !previous_stations.iter().any(|p| p.url == s.url));
+    let removed = previous_stations
+        .iter()
+        .filter(|p| !stations.iter().any(|s| s.url == p.url));
+
+    let lines: Vec<String> = added
+        .map(|s| format!("+ New station: {}", s.description()))
+        .chain(removed.map(|s| format!("- Removed station: {}", s.description())))
+        .collect();
+    if lines.is_empty() {
+        return;
     }
-    for mount in &message.station.mounts {
-        stations.push(mount.clone().into());
+
+    print_above_progress_bar(|| {
+        for line in lines {
+            writeline!("{}", theme::paint(&line, Role::Station));
+        }
+    });
+}
+
+// Falls back to all stations if none of them is a favorite. The same seed picks the same station.
+fn pick_random_station(
+    stations: &[Remote],
+    favorites_only: bool,
+    seed: Option<u64>,
+) -> Option<&Remote> {
+    let mut candidates: Vec<&Remote> = stations.iter().collect();
+    if favorites_only {
+        let config = CONFIG.lock().unwrap();
+        let favorite_stations: Vec<&Remote> = stations
+            .iter()
+            .filter(|s| config.is_favorite(s.id))
+            .collect();
+        if favorite_stations.is_empty() {
+            writeline!(
+                "{}",
+                theme::paint(
+                    "No favorite stations, picking from all stations",
+                    Role::Warning
+                )
+            );
+        } else {
+            candidates = favorite_stations;
+        }
     }
-    stations.sort_by_key(|s| s.id);
+
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    candidates.choose(&mut rng).copied()
+}
+
+// The highest bitrate station within the range. Both ends are inclusive.
+fn get_station_by_bitrate_range(
+    stations: &[Remote],
+    min_bitrate: Option<i64>,
+    max_bitrate: Option<i64>,
+) -> Option<&Remote> {
+    let range = min_bitrate.unwrap_or(i64::MIN)..=max_bitrate.unwrap_or(i64::MAX);
     stations
+        .iter()
+        .filter(|s| range.contains(&s.bitrate))
+        .max_by_key(|s| s.bitrate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn reconcile_position_moving_forward() {
+        assert_eq!(reconcile_progress_bar_position(10, 11, Some(180)), 11);
+        assert_eq!(reconcile_progress_bar_position(10, 30, Some(180)), 30);
+    }
+
+    #[test]
+    fn reconcile_position_pauses_on_drift_within_tolerance() {
+        assert_eq!(reconcile_progress_bar_position(10, 9, Some(180)), 10);
+        assert_eq!(
+            reconcile_progress_bar_position(10, 10 - PROGRESS_BAR_MAX_PAUSED_SECONDS, Some(180)),
+            10
+        );
+    }
+
+    #[test]
+    fn reconcile_position_jumps_back_on_drift_beyond_tolerance() {
+        assert_eq!(
+            reconcile_progress_bar_position(
+                10,
+                10 - PROGRESS_BAR_MAX_PAUSED_SECONDS - 1,
+                Some(180)
+            ),
+            10 - PROGRESS_BAR_MAX_PAUSED_SECONDS - 1
+        );
+        assert_eq!(reconcile_progress_bar_position(100, 0, Some(180)), 0);
+    }
+
+    #[test]
+    fn reconcile_position_does_not_overshoot_total() {
+        assert_eq!(reconcile_progress_bar_position(179, 200, Some(180)), 180);
+        assert_eq!(reconcile_progress_bar_position(179, 200, None), 200);
+    }
+
+    // `writeline!()` output, shared with `terminal::set_output()`
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedOutput {
+        fn take_lines(&self) -> Vec<String> {
+            let output = std::mem::take(&mut *self.0.lock().unwrap());
+            String::from_utf8(output)
+                .unwrap()
+                .lines()
+                .map(str::to_owned)
+                .collect()
+        }
+    }
+
+    #[test]
+    fn song_change_prints_song_info_and_progress_update_prints_nothing() {
+        colored::control::set_override(false);
+        let output = SharedOutput::default();
+        terminal::set_output(Box::new(output.clone()));
+
+        let display_options = SongInfoDisplayOptions {
+            show_peak_listeners: false,
+            song_template: None,
+            progress_mode: ProgressMode::Never,
+            progress_chars: theme::DEFAULT_PROGRESS_CHARS.to_owned(),
+            refresh_interval: Duration::from_secs(1),
+            listeners_format: ListenersFormat::Simple,
+            timestamp_format: None,
+            show_next: true,
+            mono_progress: false,
+        };
+        let mut display_state = SongInfoDisplayState::default();
+        let mut message: CodeRadioMessage =
+            serde_json::from_str(model::test_fixtures::MESSAGE_FIXTURE).unwrap();
+        let song_change = SongChange {
+            song: message.now_playing.song.clone(),
+            station_name: Some("HQ".to_owned()),
+            listeners: message.listeners.current,
+            started_at: std::time::UNIX_EPOCH,
+        };
+
+        update_song_info_on_screen(
+            message.clone(),
+            Some(&song_change),
+            &display_options,
+            &mut display_state,
+        );
+        assert_eq!(
+            output.take_lines(),
+            [
+                "",
+                "Song:       Night Drive",
+                "Artist:     Lofi Artist",
+                "Album:      Lofi Sessions",
+                "Up next:    Chill Beats - Morning Coffee",
+            ]
+        );
+
+        message.now_playing.elapsed += 10;
+        message.listeners.current += 1;
+        update_song_info_on_screen(message, None, &display_options, &mut display_state);
+        assert_eq!(output.take_lines(), Vec::<String>::new());
+    }
 }
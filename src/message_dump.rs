@@ -0,0 +1,43 @@
+use anyhow::{Context, Result};
+use std::{fs::File, io::Write, path::Path, sync::Mutex};
+
+use crate::print_above_progress_bar;
+
+static MESSAGE_DUMP: Mutex<Option<MessageDump>> = Mutex::new(None);
+
+/// Where raw websocket messages are written by `--dump-messages`. Never stdout, which is used for the UI.
+enum MessageDump {
+    Stderr,
+    File(File),
+}
+
+/// Start dumping messages to the file, or to stderr if `file_path` is `None`.
+/// An existing file is appended to.
+pub fn enable(file_path: Option<&Path>) -> Result<()> {
+    let message_dump = match file_path {
+        Some(file_path) => MessageDump::File(
+            File::options()
+                .create(true)
+                .append(true)
+                .open(file_path)
+                .with_context(|| format!("Cannot open \"{}\"", file_path.display()))?,
+        ),
+        None => MessageDump::Stderr,
+    };
+    MESSAGE_DUMP.lock().unwrap().replace(message_dump);
+    Ok(())
+}
+
+/// Write a raw websocket text message as one line, if enabled. Errors are ignored.
+pub fn dump(message_text: &str) {
+    let mut message_dump_guard = MESSAGE_DUMP.lock().unwrap();
+    match message_dump_guard.as_mut() {
+        Some(MessageDump::Stderr) => print_above_progress_bar(|| {
+            let _ = writeln!(std::io::stderr(), "{}", message_text.trim_end());
+        }),
+        Some(MessageDump::File(file)) => {
+            let _ = writeln!(file, "{}", message_text.trim_end());
+        }
+        None => {}
+    }
+}
@@ -1,19 +1,27 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
+
+// All structs below use `#[serde(default)]`, so that a missing field doesn't fail parsing
+// when the AzuraCast API changes. Unknown fields are ignored, or kept in `extra` fields.
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CodeRadioMessage {
     pub station: Station,
     pub listeners: Listeners,
     pub live: Live,
     pub now_playing: NowPlaying,
-    pub playing_next: PlayingNext,
+    pub playing_next: Option<PlayingNext>, // Not provided by some stations
     pub song_history: Vec<SongHistory>,
     pub is_online: bool,
     pub cache: String,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Station {
     pub id: i64,
     pub name: String,
@@ -32,6 +40,7 @@ pub struct Station {
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Mount {
     pub path: String,
     pub is_default: bool,
@@ -44,13 +53,15 @@ pub struct Mount {
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Listeners {
     pub total: i64,
-    pub unique: i64,
+    pub unique: Option<i64>, // Not reported by some stations
     pub current: i64,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Remote {
     pub id: i64,
     pub name: String,
@@ -61,6 +72,7 @@ pub struct Remote {
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Live {
     pub is_live: bool,
     pub streamer_name: String,
@@ -68,6 +80,7 @@ pub struct Live {
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct NowPlaying {
     pub elapsed: i64,
     pub remaining: i64,
@@ -81,6 +94,7 @@ pub struct NowPlaying {
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Song {
     pub id: String,
     pub text: String,
@@ -89,11 +103,20 @@ pub struct Song {
     pub album: String,
     pub genre: String,
     pub lyrics: String,
-    pub art: String,
-    pub custom_fields: Vec<Value>,
+    #[serde(deserialize_with = "deserialize_string_or_number")]
+    pub art: String, // May be null
+    pub custom_fields: Value, // An object, or an empty array if there are no custom fields
+    // The fields below are not always present in the API response
+    pub isrc: String,
+    #[serde(deserialize_with = "deserialize_string_or_number")]
+    pub year: String,
+    pub copyright: String,
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct PlayingNext {
     pub cued_at: i64,
     pub duration: i64,
@@ -103,6 +126,7 @@ pub struct PlayingNext {
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SongHistory {
     pub sh_id: i64,
     pub played_at: i64,
@@ -113,6 +137,78 @@ pub struct SongHistory {
     pub song: Song,
 }
 
+impl Song {
+    /// The current segment of a long show, like a part of a DJ set, from the "segment" custom field.
+    /// It may change while the song stays the same. `None` if the station doesn't provide it.
+    pub fn segment(&self) -> Option<&str> {
+        self.custom_fields
+            .get("segment")
+            .and_then(Value::as_str)
+            .map(str::trim)
+            .filter(|segment| !segment.is_empty())
+    }
+
+    /// For `--split-title`. If the artist is empty, split a title like "Artist - Track" into the artist and the title.
+    /// The first " - " separates them, so "Artist - Track - Remix" becomes "Artist" and "Track - Remix".
+    pub fn split_title(&mut self) {
+        if !self.artist.trim().is_empty() {
+            return;
+        }
+        if let Some((artist, title)) = self.title.split_once(" - ") {
+            let (artist, title) = (artist.trim().to_owned(), title.trim().to_owned());
+            if !artist.is_empty() && !title.is_empty() {
+                self.artist = artist;
+                self.title = title;
+            }
+        }
+    }
+}
+
+impl Remote {
+    /// Like "Code Radio (128 kbps MP3)". Bitrate and format are omitted if unknown.
+    pub fn description(&self) -> String {
+        let format = self.format.to_uppercase();
+        match (self.bitrate > 0, format.is_empty()) {
+            (true, false) => format!("{} ({} kbps {format})", self.name, self.bitrate),
+            (true, true) => format!("{} ({} kbps)", self.name, self.bitrate),
+            (false, false) => format!("{} ({format})", self.name),
+            (false, true) => self.name.clone(),
+        }
+    }
+}
+
+impl CodeRadioMessage {
+    /// `Song::split_title()` every song in the message
+    pub fn split_titles(&mut self) {
+        self.now_playing.song.split_title();
+        if let Some(ref mut playing_next) = self.playing_next {
+            playing_next.song.split_title();
+        }
+        for song_history in &mut self.song_history {
+            song_history.song.split_title();
+        }
+    }
+
+    /// All remotes and mounts, sorted by id.
+    /// A station may appear as both a remote and a mount with different ids.
+    /// Keep only one entry per listen url, preferring the remote.
+    pub fn get_stations(&self) -> Vec<Remote> {
+        let mut stations: Vec<Remote> = Vec::new();
+        for remote in &self.station.remotes {
+            if !stations.iter().any(|s| s.url == remote.url) {
+                stations.push(remote.clone());
+            }
+        }
+        for mount in &self.station.mounts {
+            if !stations.iter().any(|s| s.url == mount.url) {
+                stations.push(mount.clone().into());
+            }
+        }
+        stations.sort_by_key(|s| s.id);
+        stations
+    }
+}
+
 impl From<Mount> for Remote {
     fn from(mount: Mount) -> Self {
         Self {
@@ -125,3 +221,270 @@ impl From<Mount> for Remote {
         }
     }
 }
+
+// Some AzuraCast instances return a number (like `2022`), others return a string (like `"2022"`).
+// Other values like null become an empty string.
+fn deserialize_string_or_number<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Value::deserialize(deserializer)? {
+        Value::String(s) => s,
+        Value::Number(n) => n.to_string(),
+        _ => String::new(),
+    })
+}
+
+/// Builders shared by tests
+#[cfg(test)]
+pub mod test_fixtures {
+    use super::*;
+
+    /// A message of the freeCodeCamp station with two mounts and a remote
+    pub const MESSAGE_FIXTURE: &str = include_str!("../tests/fixtures/message.json");
+
+    pub fn message_playing(song_id: &str) -> CodeRadioMessage {
+        let mut message = CodeRadioMessage::default();
+        message.now_playing.song.id = song_id.to_owned();
+        message
+    }
+
+    pub fn message_with_stations(remotes: Vec<Remote>, mounts: Vec<Mount>) -> CodeRadioMessage {
+        CodeRadioMessage {
+            station: Station {
+                remotes,
+                mounts,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn remote(id: i64, url: &str) -> Remote {
+        Remote {
+            id,
+            url: url.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn named_remote(name: &str, url: &str) -> Remote {
+        Remote {
+            name: name.to_owned(),
+            ..remote(0, url)
+        }
+    }
+
+    pub fn mount(id: i64, url: &str) -> Mount {
+        Mount {
+            id,
+            url: url.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    pub fn ids(stations: &[Remote]) -> Vec<i64> {
+        stations.iter().map(|s| s.id).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{test_fixtures::*, *};
+
+    #[test]
+    fn parses_real_message() {
+        let message: CodeRadioMessage = serde_json::from_str(MESSAGE_FIXTURE).unwrap();
+        assert_eq!(message.station.shortcode, "coderadio");
+        assert_eq!(message.station.mounts.len(), 2);
+        assert_eq!(message.station.remotes.len(), 1);
+        assert_eq!(message.now_playing.song.title, "Night Drive");
+        assert_eq!(message.now_playing.song.artist, "Lofi Artist");
+        assert_eq!(message.now_playing.elapsed, 42);
+        assert_eq!(message.now_playing.duration, 182);
+        assert!(message.extra.is_empty());
+    }
+
+    #[test]
+    fn parses_partial_message_with_defaults() {
+        let message: CodeRadioMessage =
+            serde_json::from_str(r#"{ "now_playing": { "song": { "title": "Night Drive" } } }"#)
+                .unwrap();
+        assert_eq!(message.now_playing.song.title, "Night Drive");
+        assert_eq!(message.now_playing.song.artist, "");
+        assert_eq!(message.now_playing.duration, 0);
+        assert_eq!(message.listeners, Listeners::default());
+        assert_eq!(message.playing_next, None);
+        assert!(message.station.remotes.is_empty());
+    }
+
+    #[test]
+    fn keeps_unknown_fields_in_extra() {
+        let message: CodeRadioMessage = serde_json::from_str(
+            r#"{ "new_field": 1, "now_playing": { "song": { "title": "A", "bpm": 120 } } }"#,
+        )
+        .unwrap();
+        assert_eq!(message.extra.get("new_field"), Some(&Value::from(1)));
+        assert_eq!(
+            message.now_playing.song.extra.get("bpm"),
+            Some(&Value::from(120))
+        );
+    }
+
+    #[test]
+    fn parses_null_and_number_art_and_year() {
+        let message: CodeRadioMessage =
+            serde_json::from_str(r#"{ "now_playing": { "song": { "art": null, "year": 2022 } } }"#)
+                .unwrap();
+        assert_eq!(message.now_playing.song.art, "");
+        assert_eq!(message.now_playing.song.year, "2022");
+
+        let message: CodeRadioMessage =
+            serde_json::from_str(r#"{ "now_playing": { "song": { "year": "2021" } } }"#).unwrap();
+        assert_eq!(message.now_playing.song.year, "2021");
+    }
+
+    #[test]
+    fn parses_current_and_unique_listeners() {
+        let message: CodeRadioMessage =
+            serde_json::from_str(r#"{ "listeners": { "total": 12, "unique": 9, "current": 10 } }"#)
+                .unwrap();
+        assert_eq!(
+            message.listeners,
+            Listeners {
+                total: 12,
+                unique: Some(9),
+                current: 10,
+            }
+        );
+
+        let message: CodeRadioMessage =
+            serde_json::from_str(r#"{ "listeners": { "current": 10 } }"#).unwrap();
+        assert_eq!(message.listeners.unique, None);
+        assert_eq!(message.listeners.current, 10);
+    }
+
+    #[test]
+    fn get_stations_of_fixture_sorted_by_id() {
+        let message: CodeRadioMessage = serde_json::from_str(MESSAGE_FIXTURE).unwrap();
+        assert_eq!(ids(&message.get_stations()), [1, 2, 3]);
+    }
+
+    #[test]
+    fn get_stations_sorted_by_id() {
+        let message = message_with_stations(
+            vec![remote(5, "https://a"), remote(2, "https://b")],
+            vec![mount(3, "https://c"), mount(1, "https://d")],
+        );
+        assert_eq!(ids(&message.get_stations()), [1, 2, 3, 5]);
+    }
+
+    #[test]
+    fn get_stations_keeps_remote_of_duplicate_url() {
+        let message = message_with_stations(
+            vec![remote(3, "https://a"), remote(4, "https://a")],
+            vec![mount(1, "https://a"), mount(2, "https://b")],
+        );
+        assert_eq!(ids(&message.get_stations()), [2, 3]);
+    }
+
+    #[test]
+    fn get_stations_without_remotes_or_mounts() {
+        assert!(message_with_stations(vec![], vec![])
+            .get_stations()
+            .is_empty());
+        assert_eq!(
+            ids(&message_with_stations(vec![], vec![mount(1, "https://a")]).get_stations()),
+            [1]
+        );
+        assert_eq!(
+            ids(&message_with_stations(vec![remote(1, "https://a")], vec![]).get_stations()),
+            [1]
+        );
+    }
+
+    #[test]
+    fn parses_message_with_and_without_playing_next() {
+        let message: CodeRadioMessage = serde_json::from_str(
+            r#"{ "playing_next": { "cued_at": 1700000182, "song": { "title": "Morning Coffee" } } }"#,
+        )
+        .unwrap();
+        let playing_next = message.playing_next.unwrap();
+        assert_eq!(playing_next.cued_at, 1_700_000_182);
+        assert_eq!(playing_next.song.title, "Morning Coffee");
+
+        let message: CodeRadioMessage = serde_json::from_str("{}").unwrap();
+        assert_eq!(message.playing_next, None);
+        let message: CodeRadioMessage =
+            serde_json::from_str(r#"{ "playing_next": null }"#).unwrap();
+        assert_eq!(message.playing_next, None);
+    }
+
+    fn split(artist: &str, title: &str) -> (String, String) {
+        let mut song = Song {
+            artist: artist.to_owned(),
+            title: title.to_owned(),
+            ..Default::default()
+        };
+        song.split_title();
+        (song.artist, song.title)
+    }
+
+    #[test]
+    fn split_title_of_artist_and_track() {
+        assert_eq!(
+            split("", "Lofi Artist - Night Drive"),
+            ("Lofi Artist".to_owned(), "Night Drive".to_owned())
+        );
+        assert_eq!(
+            split("  ", "  Lofi Artist -  Night Drive "),
+            ("Lofi Artist".to_owned(), "Night Drive".to_owned())
+        );
+    }
+
+    #[test]
+    fn split_title_without_separator() {
+        assert_eq!(
+            split("", "Night Drive"),
+            (String::new(), "Night Drive".to_owned())
+        );
+        assert_eq!(
+            split("", "Lo-fi Beats"),
+            (String::new(), "Lo-fi Beats".to_owned())
+        );
+        assert_eq!(
+            split("", " - Night Drive"),
+            (String::new(), " - Night Drive".to_owned())
+        );
+    }
+
+    #[test]
+    fn split_title_at_the_first_of_several_separators() {
+        assert_eq!(
+            split("", "Lofi Artist - Night Drive - Remix"),
+            ("Lofi Artist".to_owned(), "Night Drive - Remix".to_owned())
+        );
+    }
+
+    #[test]
+    fn split_title_keeps_the_artist_already_set() {
+        assert_eq!(
+            split("Lofi Artist", "Chill Beats - Morning Coffee"),
+            (
+                "Lofi Artist".to_owned(),
+                "Chill Beats - Morning Coffee".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn split_titles_of_every_song_in_the_message() {
+        let mut message = CodeRadioMessage::default();
+        message.now_playing.song.title = "A - Now".to_owned();
+        message.song_history = vec![SongHistory::default()];
+        message.song_history[0].song.title = "B - Before".to_owned();
+        message.split_titles();
+        assert_eq!(message.now_playing.song.artist, "A");
+        assert_eq!(message.song_history[0].song.artist, "B");
+    }
+}
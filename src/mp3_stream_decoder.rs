@@ -1,11 +1,19 @@
 #![allow(dead_code, unused_variables, unused_mut)]
 
+use anyhow::{bail, Result};
 use minimp3::{Decoder, Frame};
-use std::io::Read;
-use std::time::Duration;
+use std::fmt::{self, Display};
+use std::io::{Chain, Cursor, Read};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::Source;
 
+/// How much of the start of a stream is read to find an MP3 frame, like after an ID3 tag
+pub const MAX_SNIFF_LEN: usize = 64 * 1024;
+
+const SNIFF_CHUNK_LEN: usize = 4096;
+
 /// This is a modified version of [rodio's Mp3Decoder](https://github.com/RustAudio/rodio/blob/55d957f8b40c59fccea4162c4b03f6dd87a7a4d9/src/decoder/mp3.rs)
 /// which removes the "Seek" trait bound for streaming network audio.
 ///
@@ -15,7 +23,7 @@ pub struct Mp3StreamDecoder<R>
 where
     R: Read,
 {
-    decoder: Decoder<R>,
+    decoder: Decoder<Chain<Cursor<Vec<u8>>, R>>,
     current_frame: Frame,
     current_frame_offset: usize,
 }
@@ -24,12 +32,21 @@ impl<R> Mp3StreamDecoder<R>
 where
     R: Read,
 {
-    pub fn new(mut data: R) -> Result<Self, R> {
-        if !is_mp3(data.by_ref()) {
-            return Err(data);
+    /// Fails if no MP3 frame is found in the first `MAX_SNIFF_LEN` bytes, like for an AAC stream
+    pub fn new(mut data: R) -> Result<Self> {
+        let mut start = Vec::new();
+        let mut chunk = [0; SNIFF_CHUNK_LEN];
+        while !contains_mp3_frame(&start) {
+            let read_len = data.read(&mut chunk)?;
+            if read_len == 0 || start.len() >= MAX_SNIFF_LEN {
+                bail!("Not an MP3 stream");
+            }
+            start.extend_from_slice(&chunk[..read_len]);
         }
-        let mut decoder = Decoder::new(data);
-        let current_frame = decoder.next_frame().unwrap();
+
+        // Decode from the start again, since sniffing has consumed it
+        let mut decoder = Decoder::new(Cursor::new(start).chain(data));
+        let current_frame = decoder.next_frame()?;
 
         Ok(Self {
             decoder,
@@ -38,7 +55,33 @@ where
         })
     }
     pub fn into_inner(self) -> R {
-        self.decoder.into_inner()
+        self.decoder.into_inner().into_inner().1
+    }
+
+    /// What the current frame is decoded as. Usually the same for the whole stream.
+    pub fn audio_format(&self) -> AudioFormat {
+        AudioFormat {
+            sample_rate: self.current_frame.sample_rate as _,
+            channels: self.current_frame.channels as _,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioFormat {
+    pub sample_rate: u32, // In Hz
+    pub channels: u16,
+}
+
+impl Display for AudioFormat {
+    /// Like "44.1 kHz stereo"
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let channels = match self.channels {
+            1 => "mono".to_owned(),
+            2 => "stereo".to_owned(),
+            n => format!("{n} channels"),
+        };
+        write!(f, "{} kHz {channels}", self.sample_rate as f64 / 1000_f64)
     }
 }
 
@@ -90,18 +133,204 @@ where
     }
 }
 
-/// Always returns true.
-fn is_mp3<R>(mut data: R) -> bool
+/// True if `data` has an MP3 frame header, followed by the header of the next frame where the first one ends
+pub fn contains_mp3_frame(data: &[u8]) -> bool {
+    (0..data.len()).any(|offset| {
+        let header = &data[offset..];
+        mp3_frame_len(header).is_some_and(|frame_len| {
+            data.get(offset + frame_len..).is_some_and(|next_header| {
+                mp3_frame_len(next_header).is_some()
+                    // Same MPEG version, layer and sample rate
+                    && next_header[1] & 0xfe == header[1] & 0xfe
+                    && next_header[2] & 0x0c == header[2] & 0x0c
+            })
+        })
+    })
+}
+
+// In bytes, from a header like "FF FB 90 00". `None` if it's not an MPEG audio frame header.
+// AAC in ADTS frames has the same sync word, but a layer of 0.
+// See http://www.mp3-tech.org/programmer/frame_header.html
+fn mp3_frame_len(header: &[u8]) -> Option<usize> {
+    const MPEG_1_BITRATES: [[u32; 14]; 3] = [
+        [
+            32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+        ],
+        [
+            32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+        ],
+        [
+            32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+        ],
+    ];
+    const MPEG_2_BITRATES: [[u32; 14]; 2] = [
+        [
+            32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+        ],
+        [8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    ];
+    const MPEG_1_SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+    let [0xff, b1, b2, ..] = *header else {
+        return None;
+    };
+    let version = (b1 >> 3) & 0b11; // 0: MPEG 2.5, 1: reserved, 2: MPEG 2, 3: MPEG 1
+    let layer = (b1 >> 1) & 0b11; // 0: reserved, 1: layer III, 2: layer II, 3: layer I
+    let bitrate_index = (b2 >> 4) as usize; // 0: free format, 15: invalid
+    let sample_rate_index = ((b2 >> 2) & 0b11) as usize; // 3: reserved
+    let padding = ((b2 >> 1) & 1) as u32;
+    if b1 & 0xe0 != 0xe0
+        || version == 1
+        || layer == 0
+        || !(1..15).contains(&bitrate_index)
+        || sample_rate_index == 3
+    {
+        return None;
+    }
+
+    let is_mpeg_1 = version == 3;
+    let layer_index = (3 - layer) as usize; // 0: layer I, 1: layer II, 2: layer III
+    let bitrate = 1000
+        * if is_mpeg_1 {
+            MPEG_1_BITRATES[layer_index][bitrate_index - 1]
+        } else {
+            MPEG_2_BITRATES[layer_index.min(1)][bitrate_index - 1]
+        };
+    let sample_rate = MPEG_1_SAMPLE_RATES[sample_rate_index] >> (3 - version).min(2);
+
+    let frame_len = match layer_index {
+        0 => (12 * bitrate / sample_rate + padding) * 4,
+        1 => 144 * bitrate / sample_rate + padding,
+        _ if is_mpeg_1 => 144 * bitrate / sample_rate + padding,
+        _ => 72 * bitrate / sample_rate + padding,
+    };
+    Some(frame_len as usize)
+}
+
+/// If reading from the stream blocks longer than this, the stream is considered rebuffering
+const BUFFERING_THRESHOLD: Duration = Duration::from_millis(800);
+
+/// Tracks whether the decoder is waiting for data from the network.
+///
+/// rodio pulls samples from `Mp3StreamDecoder` on the audio thread, and the decoder reads from the network when its
+/// current frame is used up. When the network is slow, the read blocks, rodio underruns and audio stutters.
+#[derive(Debug, Clone, Default)]
+pub struct BufferingMonitor {
+    waiting_since: Arc<Mutex<Option<Instant>>>,
+    buffer_fill: Arc<Mutex<Option<f32>>>, // Reported by `BufferAheadReader`
+}
+
+impl BufferingMonitor {
+    pub fn is_buffering(&self) -> bool {
+        self.waiting_since
+            .lock()
+            .unwrap()
+            .is_some_and(|t| t.elapsed() >= BUFFERING_THRESHOLD)
+    }
+
+    /// Call this before waiting for data, like connecting to the stream
+    pub fn start_waiting(&self) {
+        self.waiting_since
+            .lock()
+            .unwrap()
+            .get_or_insert_with(Instant::now);
+    }
+
+    pub fn stop_waiting(&self) {
+        *self.waiting_since.lock().unwrap() = None;
+    }
+
+    /// How full the read-ahead buffer is, between 0 and 1. `None` without `--buffer-ahead-seconds`.
+    pub fn buffer_fill(&self) -> Option<f32> {
+        *self.buffer_fill.lock().unwrap()
+    }
+
+    pub fn set_buffer_fill(&self, fill: f32) {
+        self.buffer_fill.lock().unwrap().replace(fill);
+    }
+}
+
+/// Wraps a reader and reports to `BufferingMonitor` while a read is blocking
+#[derive(Debug)]
+pub struct MonitoredReader<R>
 where
     R: Read,
 {
-    true
+    inner: R,
+    monitor: BufferingMonitor,
+}
 
-    // Returns true if the stream contains mp3 data, then resets it to where it was.
-    // let stream_pos = data.seek(SeekFrom::Current(0)).unwrap();
-    // let mut decoder = Decoder::new(data.by_ref());
-    // let ok = decoder.next_frame().is_ok();
-    // data.seek(SeekFrom::Start(stream_pos)).unwrap();
+impl<R> MonitoredReader<R>
+where
+    R: Read,
+{
+    pub const fn new(inner: R, monitor: BufferingMonitor) -> Self {
+        Self { inner, monitor }
+    }
+}
 
-    // ok
+impl<R> Read for MonitoredReader<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.monitor.start_waiting();
+        let result = self.inner.read(buf);
+        self.monitor.stop_waiting();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Silent MPEG-1 Layer III frames of 128 kbps, 44.1 kHz stereo, 417 bytes each
+    fn silent_mp3_frames(count: usize) -> Vec<u8> {
+        let mut frame = vec![0; 417];
+        frame[..4].copy_from_slice(&[0xff, 0xfb, 0x90, 0x00]);
+        frame.repeat(count)
+    }
+
+    // minimp3's buffer fails the checks of unsafe preconditions in debug builds, so decoding is not tested here
+    #[test]
+    fn finds_mp3_frames() {
+        assert!(contains_mp3_frame(&silent_mp3_frames(2)));
+
+        let mut data = b"ID3 tag or garbage".to_vec();
+        data.extend(silent_mp3_frames(2));
+        assert!(contains_mp3_frame(&data));
+
+        // MPEG-2 Layer III, 64 kbps, 22.05 kHz, 208 bytes each
+        let mut frame = vec![0; 208];
+        frame[..4].copy_from_slice(&[0xff, 0xf3, 0x80, 0x00]);
+        assert!(contains_mp3_frame(&frame.repeat(2)));
+    }
+
+    #[test]
+    fn needs_the_next_frame_header() {
+        let frames = silent_mp3_frames(2);
+        assert!(!contains_mp3_frame(&frames[..417]));
+        assert!(!contains_mp3_frame(&frames[..419]));
+        assert!(contains_mp3_frame(&frames[..421]));
+    }
+
+    #[test]
+    fn rejects_other_data() {
+        let not_mp3_streams = [
+            Vec::new(),
+            b"<!DOCTYPE html><html><body>Not found</body></html>".to_vec(),
+            // AAC in ADTS frames, whose sync word looks like MP3's
+            [0xff, 0xf1, 0x50, 0x80, 0x2e, 0x7f, 0xfc]
+                .into_iter()
+                .chain(std::iter::repeat(0).take(361))
+                .collect::<Vec<u8>>()
+                .repeat(8),
+            vec![0x55; MAX_SNIFF_LEN * 2],
+        ];
+        for data in not_mp3_streams {
+            let error = Mp3StreamDecoder::new(Cursor::new(data)).err().unwrap();
+            assert_eq!(error.to_string(), "Not an MP3 stream");
+        }
+    }
 }
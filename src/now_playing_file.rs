@@ -0,0 +1,44 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    print_above_progress_bar,
+    song_events::{NowPlayingSink, SongChange},
+    terminal,
+};
+
+/// For `--now-playing-file`. Overwritten with "Artist - Title" on each song change.
+#[derive(Debug, Clone)]
+pub struct NowPlayingFile {
+    path: PathBuf,
+}
+
+impl NowPlayingFile {
+    pub const fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl NowPlayingSink for NowPlayingFile {
+    fn on_song_change(&mut self, song_change: &SongChange) {
+        let song = &song_change.song;
+        let content = format!("{} - {}\n", song.artist, song.title);
+        if let Err(e) = write_atomically(&self.path, &content) {
+            print_above_progress_bar(|| terminal::print_error(format!("{e:#}")));
+        }
+    }
+
+    // It shows what's playing now, like in a stream overlay
+    fn waits_for_min_play_duration(&self) -> bool {
+        false
+    }
+}
+
+// Readers like OBS poll the file, so they should never see it half written
+fn write_atomically(path: &Path, content: &str) -> Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    std::fs::write(&temp_path, content)
+        .and_then(|()| std::fs::rename(&temp_path, path))
+        .with_context(|| format!("Cannot write now playing file \"{}\"", path.display()))
+}
@@ -0,0 +1,172 @@
+use once_cell::sync::OnceCell;
+use rodio::Source;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread,
+    time::Duration,
+};
+
+use crate::{print_above_progress_bar, terminal};
+
+// Samples are sent to the writer thread in chunks of this many samples
+const CHUNK_LEN: usize = 4096;
+
+// About 3 seconds of 44.1 kHz stereo audio. Chunks are dropped when the reader falls this far behind.
+const MAX_QUEUED_CHUNKS: usize = 32;
+
+static PCM_SENDER: OnceCell<SyncSender<PcmChunk>> = OnceCell::new();
+
+/// The format of `--pcm-out`. Samples are always interleaved signed 16-bit little-endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PcmFormat {
+    Wav, // A WAV header with the sample rate and channels of the first audio frame, and unknown length
+    Raw, // Samples only
+}
+
+struct PcmChunk {
+    sample_rate: u32,
+    channels: u16,
+    samples: Vec<i16>,
+}
+
+/// Write decoded audio to the file or FIFO in a background thread, in parallel with playback.
+/// Opening a FIFO waits for a reader in the background thread, so playback is never blocked.
+pub fn enable(path: PathBuf, format: PcmFormat) {
+    let (sender, receiver) = mpsc::sync_channel(MAX_QUEUED_CHUNKS);
+    if PCM_SENDER.set(sender).is_ok() {
+        thread::spawn(move || {
+            if let Err(e) = write_chunks(&path, format, receiver) {
+                print_above_progress_bar(|| {
+                    terminal::print_error(format!(
+                        "Stopped writing PCM to \"{}\": {e}",
+                        path.display()
+                    ));
+                });
+            }
+        });
+    }
+}
+
+/// Copy the samples of the source to `--pcm-out` while it's played. Does nothing if not enabled.
+pub fn tee<S>(source: S) -> PcmTee<S>
+where
+    S: Source<Item = i16>,
+{
+    PcmTee {
+        source,
+        sender: PCM_SENDER.get().cloned(),
+        samples: Vec::with_capacity(CHUNK_LEN),
+    }
+}
+
+fn write_chunks(
+    path: &PathBuf,
+    format: PcmFormat,
+    receiver: Receiver<PcmChunk>,
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut header_written = format == PcmFormat::Raw;
+
+    for chunk in receiver {
+        if !header_written {
+            writer.write_all(&get_wav_header(chunk.sample_rate, chunk.channels))?;
+            header_written = true;
+        }
+        for sample in chunk.samples {
+            writer.write_all(&sample.to_le_bytes())?;
+        }
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+// A 16-bit PCM WAV header. The lengths are the max value, which players treat as a stream of unknown length.
+fn get_wav_header(sample_rate: u32, channels: u16) -> Vec<u8> {
+    let block_align = channels * 2;
+    let byte_rate = sample_rate * block_align as u32;
+
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    header.extend_from_slice(b"WAVE");
+    header.extend_from_slice(b"fmt ");
+    header.extend_from_slice(&16_u32.to_le_bytes()); // Size of the "fmt " chunk
+    header.extend_from_slice(&1_u16.to_le_bytes()); // PCM
+    header.extend_from_slice(&channels.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&byte_rate.to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&16_u16.to_le_bytes()); // Bits per sample
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&u32::MAX.to_le_bytes());
+    header
+}
+
+/// A source that plays the inner source as is, and sends copies of its samples to the `--pcm-out` writer thread.
+/// If the writer falls behind, samples are dropped instead of stalling playback.
+pub struct PcmTee<S>
+where
+    S: Source<Item = i16>,
+{
+    source: S,
+    sender: Option<SyncSender<PcmChunk>>,
+    samples: Vec<i16>,
+}
+
+impl<S> Iterator for PcmTee<S>
+where
+    S: Source<Item = i16>,
+{
+    type Item = i16;
+
+    #[inline]
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.source.next()?;
+
+        if let Some(sender) = &self.sender {
+            self.samples.push(sample);
+            if self.samples.len() == CHUNK_LEN {
+                let chunk = PcmChunk {
+                    sample_rate: self.source.sample_rate(),
+                    channels: self.source.channels(),
+                    samples: std::mem::replace(&mut self.samples, Vec::with_capacity(CHUNK_LEN)),
+                };
+                // Full if the reader is slow, disconnected if writing failed
+                if let Err(mpsc::TrySendError::Disconnected(_)) = sender.try_send(chunk) {
+                    self.sender = None;
+                }
+            }
+        }
+
+        Some(sample)
+    }
+}
+
+impl<S> Source for PcmTee<S>
+where
+    S: Source<Item = i16>,
+{
+    #[inline]
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
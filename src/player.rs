@@ -1,47 +1,332 @@
 use anyhow::{Context, Result};
-use rodio::{OutputStream, Sink};
+use once_cell::sync::OnceCell;
+use rodio::{
+    cpal::{self, traits::HostTrait, Host, HostId},
+    source::SineWave,
+    DeviceTrait, OutputStream, OutputStreamHandle, Sink, Source, StreamError,
+};
 use std::{
-    sync::mpsc::{self, Sender},
+    io::Read,
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
     thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    downmix::Downmix,
+    equalizer::{EqGains, Equalizer},
+    http_client,
+    mp3_stream_decoder::{BufferingMonitor, MonitoredReader, Mp3StreamDecoder},
+    pcm_out, print_above_progress_bar,
+    stats::{CountingReader, STATS},
+    stream_buffer::BufferAheadReader,
+    stream_resume::ResumingReader,
+    terminal::{self, writeline},
+    theme::{self, Role},
 };
 
-use crate::mp3_stream_decoder::Mp3StreamDecoder;
+// How often to check whether the audio device is lost
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+// How long to wait before connecting to the stream again when it fails to open
+const STREAM_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+// A two-note chime for `--bell=chime`, quieter than the stream
+const CHIME_FREQUENCIES: [f32; 2] = [880.0, 1320.0];
+const CHIME_NOTE_DURATION: Duration = Duration::from_millis(120);
+const CHIME_AMPLITUDE: f32 = 0.2;
+
+// With `VolumeCurve::Log`, volume 1 is this many decibels quieter than volume 9
+const LOG_VOLUME_RANGE_DB: f32 = 40.0;
+
+static AUDIO_HOST: OnceCell<HostId> = OnceCell::new();
+static AUDIO_RETRIES: OnceCell<u32> = OnceCell::new();
+static BUFFER_AHEAD_SECONDS: OnceCell<u32> = OnceCell::new();
+static VOLUME_CURVE: OnceCell<VolumeCurve> = OnceCell::new();
+static CHANNELS: OnceCell<Channels> = OnceCell::new();
+static EQ_GAINS: OnceCell<EqGains> = OnceCell::new();
+
+/// How volume levels between 0 and 9 map to rodio's linear amplitude
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum VolumeCurve {
+    Linear, // Amplitude is `volume / 9`
+    Log,    // Each level is an even step in decibels, from -40 dB at 1 to 0 dB at 9. 0 is silent.
+}
+
+/// Output channel layout for `--channels`.
+/// MP3 streams have at most 2 channels, and rodio copies mono to both speakers, so `Stereo` plays the stream as is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Channels {
+    Stereo,
+    Mono,
+}
+
+/// Select the audio host (like "ALSA" or "JACK") by name, case-insensitively. Call this before creating a `Player`.
+/// If not called, cpal's default host is used.
+pub fn set_audio_host(name: &str) -> Result<()> {
+    let host_id = cpal::available_hosts()
+        .into_iter()
+        .find(|id| id.name().eq_ignore_ascii_case(name))
+        .with_context(|| {
+            format!(
+                "Audio host \"{name}\" is not available. Available hosts: {}",
+                get_available_host_names().join(", ")
+            )
+        })?;
+    let _ = AUDIO_HOST.set(host_id);
+    Ok(())
+}
+
+/// How many times to retry re-creating the output stream when the audio device is lost
+pub fn set_audio_retries(retries: u32) {
+    let _ = AUDIO_RETRIES.set(retries);
+}
+
+/// Start playing after this many seconds of audio are buffered, and keep that much buffered.
+/// If not called, audio plays as soon as it arrives.
+pub fn set_buffer_ahead_seconds(seconds: u32) {
+    let _ = BUFFER_AHEAD_SECONDS.set(seconds);
+}
+
+/// If not called, `VolumeCurve::Linear` is used
+pub fn set_volume_curve(volume_curve: VolumeCurve) {
+    let _ = VOLUME_CURVE.set(volume_curve);
+}
+
+/// If not called, the stream's own channel layout is used
+pub fn set_channels(channels: Channels) {
+    let _ = CHANNELS.set(channels);
+}
+
+/// If not called, the equalizer is off
+pub fn set_eq_gains(gains: EqGains) {
+    let _ = EQ_GAINS.set(gains);
+}
+
+/// Like `["ALSA (default)", "JACK"]`
+pub fn get_available_host_names() -> Vec<String> {
+    let default_host_id = cpal::default_host().id();
+    cpal::available_hosts()
+        .into_iter()
+        .map(|id| {
+            if id == default_host_id {
+                format!("{} (default)", id.name())
+            } else {
+                id.name().to_owned()
+            }
+        })
+        .collect()
+}
+
+// Like `OutputStream::try_default()`, on the host selected by `set_audio_host()`
+fn open_output_stream() -> Result<(OutputStream, OutputStreamHandle), StreamError> {
+    let host = get_audio_host();
+    let default_device = host.default_output_device().ok_or(StreamError::NoDevice)?;
+
+    // If the default device doesn't work, try other ones
+    OutputStream::try_from_device(&default_device).or_else(|original_err| {
+        host.output_devices()
+            .ok()
+            .and_then(|mut devices| devices.find_map(|d| OutputStream::try_from_device(&d).ok()))
+            .ok_or(original_err)
+    })
+}
+
+// Called when the audio device is lost. Retries `--audio-retries` times.
+fn reopen_output_stream() -> Option<(OutputStream, OutputStreamHandle)> {
+    print_above_progress_bar(|| {
+        writeline!(
+            "{}",
+            theme::paint("Audio device changed, reconnecting...", Role::Warning)
+        );
+    });
+
+    let retries = AUDIO_RETRIES.get().copied().unwrap_or(0);
+    let mut attempt = 0;
+    loop {
+        match open_output_stream() {
+            Ok(result) => return Some(result),
+            Err(e) if attempt == retries => {
+                print_above_progress_bar(|| {
+                    terminal::print_error(format!("Audio device initialization failed: {e}"));
+                });
+                return None;
+            }
+            Err(_) => {
+                attempt += 1;
+                thread::sleep(Duration::from_secs(2));
+            }
+        }
+    }
+}
+
+// The audio device is considered lost when the default device changes,
+// like when the device is unplugged, or headphones are connected
+fn get_default_output_device_name() -> Option<String> {
+    get_audio_host()
+        .default_output_device()
+        .and_then(|device| device.name().ok())
+}
+
+// From the "icy-br" header like "128" or "128,128", in kbps
+fn get_icecast_bitrate(response: &reqwest::blocking::Response) -> Option<u32> {
+    response
+        .headers()
+        .get("icy-br")?
+        .to_str()
+        .ok()?
+        .split(',')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+fn get_audio_host() -> Host {
+    AUDIO_HOST
+        .get()
+        .and_then(|host_id| cpal::host_from_id(*host_id).ok())
+        .unwrap_or_else(cpal::default_host)
+}
 
 pub struct Player {
     sender: Sender<PlayerMessage>,
     volume: u8, // Between 0 and 9
+    paused: bool,
+    buffering_monitor: BufferingMonitor,
+    first_frame_decoded_at: Arc<Mutex<Option<Instant>>>, // For `--bench`
+    stream_failed: Arc<Mutex<bool>>,
 }
 
 enum PlayerMessage {
     Play { listen_url: String, volume: u8 },
     Volume { volume: u8 },
+    Pause { paused: bool },
+    Reconnect,
+    Chime,
 }
 
 impl Player {
     /// Creating a `Player` might be time consuming. It might take several seconds on first run.
     pub fn try_new() -> Result<Self> {
-        OutputStream::try_default().context("Audio device initialization failed")?;
+        open_output_stream().context("Audio device initialization failed")?;
 
         let (sender, receiver) = mpsc::channel();
+        let buffering_monitor = BufferingMonitor::default();
+        let thread_buffering_monitor = buffering_monitor.clone();
+        let first_frame_decoded_at = Arc::new(Mutex::new(None));
+        let thread_first_frame_decoded_at = first_frame_decoded_at.clone();
+        let stream_failed = Arc::new(Mutex::new(false));
+        let thread_stream_failed = stream_failed.clone();
         thread::spawn(move || {
-            let (_stream, stream_handle) = OutputStream::try_default().unwrap();
+            let buffering_monitor = thread_buffering_monitor;
+            let first_frame_decoded_at = thread_first_frame_decoded_at;
+            let stream_failed = thread_stream_failed;
+
+            let (mut _output_stream, mut stream_handle) = open_output_stream().unwrap();
+            let stream_client = http_client::build_stream_client().unwrap();
+            let mut default_device_name = get_default_output_device_name();
 
+            let mut paused = false;
             let (mut current_listen_url, mut current_volume) = loop {
-                if let Ok(PlayerMessage::Play { listen_url, volume }) = receiver.recv() {
-                    break (listen_url, volume);
+                match receiver.recv() {
+                    Ok(PlayerMessage::Play { listen_url, volume }) => break (listen_url, volume),
+                    Ok(PlayerMessage::Pause { paused: new_paused }) => paused = new_paused,
+                    Ok(_) => {}
+                    Err(_) => return, // `Player` is dropped
                 }
             };
 
             loop {
-                let response = reqwest::blocking::get(&current_listen_url).unwrap();
-                let source = Mp3StreamDecoder::new(response).unwrap();
+                // Disconnected while paused, so that resuming plays live and no data is downloaded meanwhile
+                while paused {
+                    match receiver.recv() {
+                        Ok(PlayerMessage::Play { listen_url, volume }) => {
+                            current_listen_url = listen_url;
+                            current_volume = volume;
+                        }
+                        Ok(PlayerMessage::Volume { volume }) => current_volume = volume,
+                        Ok(PlayerMessage::Pause { paused: new_paused }) => paused = new_paused,
+                        Ok(PlayerMessage::Reconnect | PlayerMessage::Chime) => {}
+                        Err(_) => return, // `Player` is dropped
+                    }
+                }
+
+                buffering_monitor.start_waiting();
+                let source = match Self::open_stream(
+                    &stream_client,
+                    &current_listen_url,
+                    buffering_monitor.clone(),
+                ) {
+                    Ok(source) => {
+                        *stream_failed.lock().unwrap() = false;
+                        source
+                    }
+                    Err(_) => {
+                        *stream_failed.lock().unwrap() = true;
+                        // Try again later, or right away when told to play another stream
+                        match receiver.recv_timeout(STREAM_RETRY_INTERVAL) {
+                            Ok(PlayerMessage::Play { listen_url, volume }) => {
+                                current_listen_url = listen_url;
+                                current_volume = volume;
+                            }
+                            Ok(PlayerMessage::Volume { volume }) => current_volume = volume,
+                            Ok(PlayerMessage::Pause { paused: new_paused }) => paused = new_paused,
+                            Ok(PlayerMessage::Reconnect | PlayerMessage::Chime)
+                            | Err(RecvTimeoutError::Timeout) => {}
+                            Err(RecvTimeoutError::Disconnected) => return, // `Player` is dropped
+                        }
+                        continue;
+                    }
+                };
+                STATS.lock().unwrap().audio_format = Some(source.audio_format());
+                first_frame_decoded_at
+                    .lock()
+                    .unwrap()
+                    .get_or_insert_with(Instant::now);
                 let sink = Sink::try_new(&stream_handle).unwrap();
-                sink.append(source);
+                let source = Downmix::new(source, CHANNELS.get() == Some(&Channels::Mono));
+                let source = Equalizer::new(source, EQ_GAINS.get().copied());
+                sink.append(pcm_out::tee(source));
                 sink.set_volume(Self::map_volume_to_rodio_volume(current_volume));
 
-                while let Ok(message) = receiver.recv() {
+                loop {
+                    let message = match receiver.recv_timeout(DEVICE_CHECK_INTERVAL) {
+                        Ok(message) => message,
+                        Err(RecvTimeoutError::Timeout) => {
+                            // The stream ended, like when the connection dropped and couldn't be resumed.
+                            // Open it again, and let `preferred_stations` in the config fail over meanwhile.
+                            if sink.empty() {
+                                *stream_failed.lock().unwrap() = true;
+                                break;
+                            }
+                            let new_default_device_name = get_default_output_device_name();
+                            if new_default_device_name == default_device_name {
+                                continue;
+                            }
+                            // If it fails, try again when the default device changes again
+                            default_device_name = new_default_device_name;
+                            let Some((output_stream, new_stream_handle)) = reopen_output_stream()
+                            else {
+                                continue;
+                            };
+                            _output_stream = output_stream;
+                            stream_handle = new_stream_handle;
+                            break; // Reconnect to resume at the live position
+                        }
+                        Err(RecvTimeoutError::Disconnected) => return, // `Player` is dropped
+                    };
+
                     match message {
                         PlayerMessage::Play { listen_url, volume } => {
+                            // Keep the connection and the sink of the stream already playing,
+                            // so that there's no gap
+                            if listen_url == current_listen_url {
+                                continue;
+                            }
                             current_listen_url = listen_url;
                             current_volume = volume;
                             break;
@@ -50,14 +335,55 @@ impl Player {
                             current_volume = volume;
                             sink.set_volume(Self::map_volume_to_rodio_volume(current_volume));
                         }
+                        PlayerMessage::Pause { paused: true } => {
+                            paused = true;
+                            break;
+                        }
+                        PlayerMessage::Pause { paused: false } => {}
+                        PlayerMessage::Reconnect => break,
+                        PlayerMessage::Chime => {
+                            Self::play_chime_on(&stream_handle, current_volume);
+                        }
                     }
                 }
             }
         });
 
-        Ok(Self { sender, volume: 9 })
+        Ok(Self {
+            sender,
+            volume: 9,
+            paused: false,
+            buffering_monitor,
+            first_frame_decoded_at,
+            stream_failed,
+        })
+    }
+
+    // Connect to the stream and start decoding it
+    fn open_stream(
+        stream_client: &reqwest::blocking::Client,
+        listen_url: &str,
+        buffering_monitor: BufferingMonitor,
+    ) -> Result<Mp3StreamDecoder<MonitoredReader<Box<dyn Read + Send>>>> {
+        let response = stream_client.get(listen_url).send()?.error_for_status()?;
+        let bitrate = get_icecast_bitrate(&response);
+        let response = ResumingReader::new(stream_client.clone(), listen_url, response);
+        let response_reader: Box<dyn Read + Send> = match BUFFER_AHEAD_SECONDS.get() {
+            Some(seconds) => Box::new(BufferAheadReader::new(
+                CountingReader::new(response),
+                *seconds,
+                bitrate,
+                buffering_monitor.clone(),
+            )),
+            None => Box::new(CountingReader::new(response)),
+        };
+        let reader = MonitoredReader::new(response_reader, buffering_monitor);
+        Mp3StreamDecoder::new(reader)
     }
 
+    /// Start playing the stream. The stream is continuous across songs,
+    /// so this only needs to be called when the station changes, not on song changes.
+    /// Playing the url already playing does nothing.
     pub fn play(&self, listen_url: &str) {
         self.sender
             .send(PlayerMessage::Play {
@@ -67,6 +393,63 @@ impl Player {
             .unwrap();
     }
 
+    /// Stop playing and disconnect until resumed. Resuming plays the live stream, not where it was paused.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.sender.send(PlayerMessage::Pause { paused }).unwrap();
+    }
+
+    pub const fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Reconnect to the stream currently playing. Does nothing if not playing yet.
+    pub fn reconnect(&self) {
+        self.sender.send(PlayerMessage::Reconnect).unwrap();
+    }
+
+    /// Play a short tone over the stream, like on song changes. Does nothing if not playing yet.
+    pub fn play_chime(&self) {
+        self.sender.send(PlayerMessage::Chime).unwrap();
+    }
+
+    // In a separate sink, which rodio mixes with the stream
+    fn play_chime_on(stream_handle: &OutputStreamHandle, volume: u8) {
+        let Ok(sink) = Sink::try_new(stream_handle) else {
+            return;
+        };
+        for frequency in CHIME_FREQUENCIES {
+            sink.append(
+                SineWave::new(frequency)
+                    .take_duration(CHIME_NOTE_DURATION)
+                    .amplify(CHIME_AMPLITUDE),
+            );
+        }
+        sink.set_volume(Self::map_volume_to_rodio_volume(volume));
+        sink.detach();
+    }
+
+    /// When the first audio frame of the stream was decoded. `None` if not yet.
+    pub fn first_frame_decoded_at(&self) -> Option<Instant> {
+        *self.first_frame_decoded_at.lock().unwrap()
+    }
+
+    /// True if the stream couldn't be opened or stopped playing, like when the server is down or it's
+    /// not an MP3 stream. The player keeps trying to open it again.
+    pub fn has_stream_failed(&self) -> bool {
+        *self.stream_failed.lock().unwrap()
+    }
+
+    /// How full the `--buffer-ahead-seconds` buffer is, between 0 and 1. `None` without it.
+    pub fn buffer_fill(&self) -> Option<f32> {
+        self.buffering_monitor.buffer_fill()
+    }
+
+    /// True if the audio stream is waiting for data from the network
+    pub fn is_buffering(&self) -> bool {
+        self.buffering_monitor.is_buffering()
+    }
+
     pub const fn volume(&self) -> u8 {
         self.volume
     }
@@ -86,8 +469,14 @@ impl Player {
         volume.min(9)
     }
 
-    /// Map a volume between 0 and 9 to between 0 and 1
+    /// Map a volume between 0 and 9 to between 0 and 1, according to the volume curve
     fn map_volume_to_rodio_volume(volume: u8) -> f32 {
-        volume as f32 / 9_f32
+        match VOLUME_CURVE.get() {
+            Some(VolumeCurve::Log) if volume > 0 => {
+                let decibels = (volume as f32 - 9_f32) / 8_f32 * LOG_VOLUME_RANGE_DB;
+                10_f32.powf(decibels / 20_f32)
+            }
+            _ => volume as f32 / 9_f32,
+        }
     }
 }
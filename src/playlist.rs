@@ -0,0 +1,41 @@
+use anyhow::{Context, Result};
+use std::{fmt::Write, path::Path};
+
+use crate::model::Remote;
+
+/// Write the stations as an extended M3U playlist, for use in other media players like VLC
+pub async fn write_m3u(path: &Path, stations: &[Remote]) -> Result<()> {
+    tokio::fs::write(path, to_m3u(stations))
+        .await
+        .with_context(|| format!("Cannot write playlist \"{}\"", path.display()))
+}
+
+fn to_m3u(stations: &[Remote]) -> String {
+    let mut playlist = String::from("#EXTM3U\n");
+    for station in stations {
+        // Duration -1 means a stream of unknown length
+        // A line break would start a new entry
+        let title = station.description().replace(['\r', '\n'], " ");
+        let _ = writeln!(playlist, "#EXTINF:-1,{title}");
+        let _ = writeln!(playlist, "{}", escape_url(&station.url));
+    }
+    playlist
+}
+
+// Percent-encode characters that would break the line or confuse players. Already encoded urls are kept as is.
+fn escape_url(url: &str) -> String {
+    let mut escaped = String::with_capacity(url.len());
+    for byte in url.bytes() {
+        if byte.is_ascii_graphic()
+            && !matches!(
+                byte,
+                b'"' | b'<' | b'>' | b'\\' | b'^' | b'`' | b'{' | b'|' | b'}'
+            )
+        {
+            escaped.push(byte as char);
+        } else {
+            let _ = write!(escaped, "%{byte:02X}");
+        }
+    }
+    escaped
+}
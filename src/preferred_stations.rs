@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+use crate::model::Remote;
+
+/// An entry of `preferred_stations` in the config file: a station id like `1`,
+/// or a station name like `"Code Radio (mirror)"`, which is matched case-insensitively
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StationPreference {
+    Id(i64),
+    Name(String),
+}
+
+impl StationPreference {
+    pub fn matches(&self, station: &Remote) -> bool {
+        match self {
+            Self::Id(id) => station.id == *id,
+            Self::Name(name) => station.name.eq_ignore_ascii_case(name),
+        }
+    }
+}
+
+/// A station picked by preference. The level is its position in the config, starting from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreferredStation {
+    pub level: usize,
+    pub station: Remote,
+}
+
+/// The preferred stations among `stations`, most preferred first.
+/// Preferences without a matching station are skipped.
+pub fn find(preferences: &[StationPreference], stations: &[Remote]) -> Vec<PreferredStation> {
+    preferences
+        .iter()
+        .enumerate()
+        .filter_map(|(i, preference)| {
+            let station = stations.iter().find(|s| preference.matches(s))?;
+            Some(PreferredStation {
+                level: i + 1,
+                station: station.clone(),
+            })
+        })
+        .collect()
+}
+
+/// The level of the first preference matching the station. `None` if it's not preferred.
+pub fn level_of(preferences: &[StationPreference], station: &Remote) -> Option<usize> {
+    preferences
+        .iter()
+        .position(|preference| preference.matches(station))
+        .map(|i| i + 1)
+}
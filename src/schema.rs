@@ -0,0 +1,198 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+use crate::model::{CodeRadioMessage, Mount, PlayingNext, Remote, SongHistory, Station};
+
+// Fields the app works without, since some stations don't provide them. They may be missing or null.
+const OPTIONAL_FIELDS: [&str; 5] = ["playing_next", "unique", "isrc", "year", "copyright"];
+
+// Fields that are null for some songs
+const NULLABLE_FIELDS: [&str; 1] = ["art"];
+
+// Fields that are a number for some AzuraCast instances, like `"year": 2022`
+const STRING_OR_NUMBER_FIELDS: [&str; 2] = ["art", "year"];
+
+/// For `--strict`. Check that the message has exactly the fields of `CodeRadioMessage`, no more and no less,
+/// and that they have the expected types. The error lists each offending field by its JSON path, like
+/// "station.mounts[0].bitrate".
+pub fn validate(message_text: &str) -> Result<()> {
+    let message: Value = serde_json::from_str(message_text)?;
+
+    let mut problems = Vec::new();
+    compare(&message, &expected_shape(), "", &mut problems);
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Message doesn't match the expected format:\n  {}",
+            problems.join("\n  ")
+        ))
+    }
+}
+
+// A message with one element in each list and every optional field present, so that all fields are serialized
+fn expected_shape() -> Value {
+    let message = CodeRadioMessage {
+        station: Station {
+            mounts: vec![Mount::default()],
+            remotes: vec![Remote::default()],
+            ..Default::default()
+        },
+        playing_next: Some(PlayingNext::default()),
+        song_history: vec![SongHistory::default()],
+        ..Default::default()
+    };
+    serde_json::to_value(message).unwrap_or_default()
+}
+
+fn compare(value: &Value, expected: &Value, path: &str, problems: &mut Vec<String>) {
+    match (value, expected) {
+        (Value::Object(fields), Value::Object(expected_fields)) => {
+            for (key, expected_value) in expected_fields {
+                let field_path = join_path(path, key);
+                let key = key.as_str();
+                match fields.get(key) {
+                    Some(Value::Null)
+                        if OPTIONAL_FIELDS.contains(&key) || NULLABLE_FIELDS.contains(&key) => {}
+                    Some(Value::Number(_)) if STRING_OR_NUMBER_FIELDS.contains(&key) => {}
+                    Some(value) => compare(value, expected_value, &field_path, problems),
+                    None if OPTIONAL_FIELDS.contains(&key) => {}
+                    None => problems.push(format!("Missing field \"{field_path}\"")),
+                }
+            }
+            for key in fields
+                .keys()
+                .filter(|key| !expected_fields.contains_key(*key))
+            {
+                problems.push(format!("Unknown field \"{}\"", join_path(path, key)));
+            }
+        }
+        (Value::Array(elements), Value::Array(expected_elements)) => {
+            if let Some(expected_element) = expected_elements.first() {
+                for (i, element) in elements.iter().enumerate() {
+                    compare(element, expected_element, &format!("{path}[{i}]"), problems);
+                }
+            }
+        }
+        // A null in the expected shape is a field of any type, like `custom_fields`
+        (_, Value::Null) => {}
+        _ if type_name(value) != type_name(expected) => problems.push(format!(
+            "Field \"{path}\" is {}, expected {}",
+            type_name(value),
+            type_name(expected)
+        )),
+        _ => {}
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "a list",
+        Value::Object(_) => "an object",
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_fixtures::MESSAGE_FIXTURE;
+
+    // The message fixture with `edit` applied
+    fn problems_of(edit: impl FnOnce(&mut Value)) -> Vec<String> {
+        let mut message: Value = serde_json::from_str(MESSAGE_FIXTURE).unwrap();
+        edit(&mut message);
+        match validate(&message.to_string()) {
+            Ok(()) => Vec::new(),
+            Err(e) => e
+                .to_string()
+                .lines()
+                .skip(1)
+                .map(|line| line.trim().to_owned())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn accepts_the_fixture() {
+        assert!(problems_of(|_| {}).is_empty());
+    }
+
+    #[test]
+    fn reports_missing_and_unknown_fields_by_path() {
+        let problems = problems_of(|message| {
+            let mount = &mut message["station"]["mounts"][1];
+            mount.as_object_mut().unwrap().remove("bitrate");
+            mount["codec"] = Value::from("mp3");
+        });
+        assert_eq!(
+            problems,
+            [
+                "Missing field \"station.mounts[1].bitrate\"",
+                "Unknown field \"station.mounts[1].codec\""
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unexpected_nulls() {
+        let problems = problems_of(|message| {
+            message["now_playing"]["song"]["title"] = Value::Null;
+            message["station"]["remotes"][0] = Value::Null;
+        });
+        assert_eq!(
+            problems,
+            [
+                "Field \"now_playing.song.title\" is null, expected a string",
+                "Field \"station.remotes[0]\" is null, expected an object"
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_nulls_of_optional_and_nullable_fields() {
+        let problems = problems_of(|message| {
+            message["playing_next"] = Value::Null;
+            message["now_playing"]["song"]["art"] = Value::Null;
+            message["now_playing"]["song"]["year"] = Value::Null;
+        });
+        assert!(problems.is_empty(), "{problems:?}");
+    }
+
+    #[test]
+    fn reports_scalar_type_mismatches() {
+        let problems = problems_of(|message| {
+            message["now_playing"]["elapsed"] = Value::from("42");
+            message["station"]["mounts"][0]["bitrate"] = Value::from(true);
+            message["listeners"]["current"] = Value::from(vec![1]);
+        });
+        assert_eq!(
+            problems,
+            [
+                "Field \"listeners.current\" is a list, expected a number",
+                "Field \"now_playing.elapsed\" is a string, expected a number",
+                "Field \"station.mounts[0].bitrate\" is a boolean, expected a number"
+            ]
+        );
+    }
+
+    #[test]
+    fn accepts_numbers_of_string_or_number_fields() {
+        let problems = problems_of(|message| {
+            message["now_playing"]["song"]["year"] = Value::from(2022);
+        });
+        assert!(problems.is_empty(), "{problems:?}");
+    }
+}
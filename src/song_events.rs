@@ -0,0 +1,290 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::model::{CodeRadioMessage, Song};
+
+/// A genuine track transition
+#[derive(Debug, Clone)]
+pub struct SongChange {
+    pub song: Song,
+    pub station_name: Option<String>,
+    pub listeners: i64,
+    pub started_at: SystemTime, // As reported by the API, or when the change was received if unknown
+}
+
+/// An integration reacting to song changes, like the webhook or the now playing file.
+/// Register it with `SongChangeDispatcher::register()`.
+pub trait NowPlayingSink {
+    /// Called on the main loop, so slow work like network requests should be spawned
+    fn on_song_change(&mut self, song_change: &SongChange);
+
+    /// True for integrations recording what was played, like the webhook, which are only notified of
+    /// songs played for the minimum play duration. False for displays like the now playing file,
+    /// which are notified of every change right away.
+    fn waits_for_min_play_duration(&self) -> bool {
+        true
+    }
+}
+
+/// Tracks the last song id in one place, so that side effects like webhooks happen exactly once per song,
+/// even if the same song is received again, like after reconnecting.
+///
+/// Sinks waiting for the minimum play duration are only notified after a song has been playing that long,
+/// so that songs skipped by a flapping connection don't pollute integrations. Other sinks are notified right away.
+///
+/// Integrations should implement `NowPlayingSink` instead of checking song ids themselves.
+pub struct SongChangeDispatcher {
+    min_play_duration: Duration,
+    last_song_id: Option<String>,
+    last_committed_song_id: Option<String>,
+    pending: Option<(SongChange, Instant)>, // Not committed yet, and when it became current
+    sinks: Vec<Box<dyn NowPlayingSink>>,
+}
+
+impl SongChangeDispatcher {
+    pub fn new(min_play_duration: Duration) -> Self {
+        Self {
+            min_play_duration,
+            last_song_id: None,
+            last_committed_song_id: None,
+            pending: None,
+            sinks: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, sink: impl NowPlayingSink + 'static) {
+        self.sinks.push(Box::new(sink));
+    }
+
+    /// Call this on every API message. Returns the change right away if the song changed, like for display.
+    /// Sinks waiting for the minimum play duration are notified once the song has been current that long,
+    /// when `commit_pending()` is called at `pending_commit_at()`.
+    pub fn dispatch(
+        &mut self,
+        message: &CodeRadioMessage,
+        station_name: Option<String>,
+    ) -> Option<SongChange> {
+        self.dispatch_at(message, station_name, Instant::now())
+    }
+
+    fn dispatch_at(
+        &mut self,
+        message: &CodeRadioMessage,
+        station_name: Option<String>,
+        now: Instant,
+    ) -> Option<SongChange> {
+        let song = &message.now_playing.song;
+        let song_change = if self.last_song_id.as_ref() == Some(&song.id) {
+            None
+        } else {
+            self.last_song_id = Some(song.id.clone());
+            // The previous song may have reached the minimum play duration since the last commit
+            self.commit_pending_at(now);
+
+            let song_change = SongChange {
+                song: song.clone(),
+                station_name,
+                listeners: message.listeners.current,
+                started_at: get_started_at(message.now_playing.played_at),
+            };
+            for sink in self
+                .sinks
+                .iter_mut()
+                .filter(|s| !s.waits_for_min_play_duration())
+            {
+                sink.on_song_change(&song_change);
+            }
+            self.pending = Some((song_change.clone(), now));
+            Some(song_change)
+        };
+
+        self.commit_pending_at(now);
+        song_change
+    }
+
+    /// When the pending song reaches the minimum play duration. `None` if no song is pending.
+    pub fn pending_commit_at(&self) -> Option<Instant> {
+        self.pending
+            .as_ref()
+            .map(|(_, became_current_at)| *became_current_at + self.min_play_duration)
+    }
+
+    /// Notify sinks waiting for the minimum play duration of the pending song, if it has been current that long
+    pub fn commit_pending(&mut self) {
+        self.commit_pending_at(Instant::now());
+    }
+
+    fn commit_pending_at(&mut self, now: Instant) {
+        let Some((_, became_current_at)) = &self.pending else {
+            return;
+        };
+        if now.duration_since(*became_current_at) < self.min_play_duration {
+            return;
+        }
+
+        let (song_change, _) = self.pending.take().unwrap();
+        // Like when flapping between two songs
+        if self.last_committed_song_id.as_ref() == Some(&song_change.song.id) {
+            return;
+        }
+        self.last_committed_song_id = Some(song_change.song.id.clone());
+        for sink in self
+            .sinks
+            .iter_mut()
+            .filter(|s| s.waits_for_min_play_duration())
+        {
+            sink.on_song_change(&song_change);
+        }
+    }
+}
+
+// `played_at` is a Unix timestamp in seconds, 0 if unknown
+fn get_started_at(played_at: i64) -> SystemTime {
+    u64::try_from(played_at)
+        .ok()
+        .filter(|seconds| *seconds > 0)
+        .map_or_else(SystemTime::now, |seconds| {
+            UNIX_EPOCH + Duration::from_secs(seconds)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_fixtures::message_playing;
+    use std::{cell::RefCell, rc::Rc};
+
+    const MIN_PLAY_DURATION: Duration = Duration::from_secs(20);
+
+    // Records the ids of songs it's notified of
+    struct RecordingSink {
+        song_ids: Rc<RefCell<Vec<String>>>,
+        waits_for_min_play_duration: bool,
+    }
+
+    impl NowPlayingSink for RecordingSink {
+        fn on_song_change(&mut self, song_change: &SongChange) {
+            self.song_ids.borrow_mut().push(song_change.song.id.clone());
+        }
+
+        fn waits_for_min_play_duration(&self) -> bool {
+            self.waits_for_min_play_duration
+        }
+    }
+
+    fn register_recording_sink(
+        dispatcher: &mut SongChangeDispatcher,
+        waits_for_min_play_duration: bool,
+    ) -> Rc<RefCell<Vec<String>>> {
+        let song_ids = Rc::new(RefCell::new(Vec::new()));
+        dispatcher.register(RecordingSink {
+            song_ids: song_ids.clone(),
+            waits_for_min_play_duration,
+        });
+        song_ids
+    }
+
+    fn new_dispatcher() -> (SongChangeDispatcher, Rc<RefCell<Vec<String>>>) {
+        let mut dispatcher = SongChangeDispatcher::new(MIN_PLAY_DURATION);
+        let committed = register_recording_sink(&mut dispatcher, true);
+        (dispatcher, committed)
+    }
+
+    #[test]
+    fn commits_after_min_play_duration_without_another_message() {
+        let (mut dispatcher, committed) = new_dispatcher();
+        let start = Instant::now();
+
+        assert!(dispatcher
+            .dispatch_at(&message_playing("a"), None, start)
+            .is_some());
+        assert!(committed.borrow().is_empty());
+        assert_eq!(
+            dispatcher.pending_commit_at(),
+            Some(start + MIN_PLAY_DURATION)
+        );
+
+        dispatcher.commit_pending_at(start + MIN_PLAY_DURATION);
+        assert_eq!(*committed.borrow(), ["a"]);
+        assert_eq!(dispatcher.pending_commit_at(), None);
+    }
+
+    #[test]
+    fn same_song_again_is_not_a_change() {
+        let (mut dispatcher, committed) = new_dispatcher();
+        let start = Instant::now();
+
+        dispatcher.dispatch_at(&message_playing("a"), None, start);
+        assert!(dispatcher
+            .dispatch_at(&message_playing("a"), None, start + Duration::from_secs(5))
+            .is_none());
+        dispatcher.commit_pending_at(start + MIN_PLAY_DURATION);
+        dispatcher.dispatch_at(&message_playing("a"), None, start + MIN_PLAY_DURATION * 2);
+        assert_eq!(*committed.borrow(), ["a"]);
+    }
+
+    #[test]
+    fn flapping_between_songs_commits_nothing() {
+        let (mut dispatcher, committed) = new_dispatcher();
+        let start = Instant::now();
+        dispatcher.dispatch_at(&message_playing("a"), None, start);
+        dispatcher.commit_pending_at(start + MIN_PLAY_DURATION);
+
+        // A -> B -> A within the minimum play duration
+        let flap_start = start + MIN_PLAY_DURATION * 2;
+        assert!(dispatcher
+            .dispatch_at(&message_playing("b"), None, flap_start)
+            .is_some());
+        assert!(dispatcher
+            .dispatch_at(
+                &message_playing("a"),
+                None,
+                flap_start + Duration::from_secs(2)
+            )
+            .is_some());
+        dispatcher.commit_pending_at(flap_start + MIN_PLAY_DURATION * 2);
+
+        assert_eq!(*committed.borrow(), ["a"]);
+    }
+
+    #[test]
+    fn sinks_not_waiting_are_notified_of_every_change_right_away() {
+        let (mut dispatcher, committed) = new_dispatcher();
+        let displayed = register_recording_sink(&mut dispatcher, false);
+        let start = Instant::now();
+
+        dispatcher.dispatch_at(&message_playing("a"), None, start);
+        assert_eq!(*displayed.borrow(), ["a"]);
+        dispatcher.dispatch_at(&message_playing("a"), None, start + Duration::from_secs(1));
+        dispatcher.dispatch_at(&message_playing("b"), None, start + Duration::from_secs(2));
+        assert_eq!(*displayed.borrow(), ["a", "b"]);
+        assert!(committed.borrow().is_empty());
+
+        dispatcher.commit_pending_at(start + MIN_PLAY_DURATION * 2);
+        assert_eq!(*committed.borrow(), ["b"]);
+        assert_eq!(*displayed.borrow(), ["a", "b"]);
+    }
+
+    #[test]
+    fn started_at_is_when_the_api_says_the_song_was_played() {
+        let (mut dispatcher, _) = new_dispatcher();
+        let mut played = message_playing("a");
+        played.now_playing.played_at = 1_700_000_000;
+        let song_change = dispatcher
+            .dispatch_at(&played, None, Instant::now())
+            .unwrap();
+        assert_eq!(
+            song_change.started_at,
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+    }
+
+    #[test]
+    fn started_at_is_when_received_if_unknown() {
+        let (mut dispatcher, _) = new_dispatcher();
+        let received_after = SystemTime::now();
+        let song_change = dispatcher
+            .dispatch_at(&message_playing("a"), None, Instant::now())
+            .unwrap();
+        assert!(song_change.started_at >= received_after);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::{
+    model::{CodeRadioMessage, Song},
+    utils,
+};
+
+// The name of the station the API message's default listen url belongs to
+pub fn get_station_name_from_api_message(message: &CodeRadioMessage) -> String {
+    message
+        .get_stations()
+        .into_iter()
+        .find(|s| s.url == message.station.listen_url)
+        .map_or_else(|| message.station.name.clone(), |s| s.name)
+}
+
+// Values of the placeholders in `--format` and `--oneshot-format` templates
+pub fn get_template_values(
+    message: &CodeRadioMessage,
+    station_name: &str,
+) -> Vec<(&'static str, String)> {
+    let song = &message.now_playing.song;
+    let humanize_seconds =
+        |seconds: i64| utils::humanize_seconds_to_minutes_and_seconds(seconds.max(0) as u64);
+
+    vec![
+        ("title", song.title.clone()),
+        ("artist", song.artist.clone()),
+        ("album", song.album.clone()),
+        ("genre", song.genre.clone()),
+        ("year", song.year.clone()),
+        ("station", station_name.to_owned()),
+        ("listeners", message.listeners.current.to_string()),
+        ("elapsed", humanize_seconds(message.now_playing.elapsed)),
+        ("duration", humanize_seconds(message.now_playing.duration)),
+    ]
+}
+
+pub fn get_next_song(message: &CodeRadioMessage) -> Option<&Song> {
+    message
+        .playing_next
+        .as_ref()
+        .map(|playing_next| &playing_next.song)
+        .filter(|song| !song.id.is_empty())
+}
+
+// Like `[("Genre:", "Lofi"), ("Year:", "2022")]`. Only the metadata fields provided by the API.
+// The text is left out if it's just "Artist - Title".
+pub fn get_extra_song_metadata(song: &Song) -> Vec<(&'static str, &str)> {
+    let text_differs_from_artist_and_title =
+        song.text != format!("{} - {}", song.artist, song.title);
+
+    let fields = [
+        ("Genre:", song.genre.as_str()),
+        ("Year:", song.year.as_str()),
+        ("ISRC:", song.isrc.as_str()),
+        ("Copyright:", song.copyright.as_str()),
+        (
+            "Text:",
+            if text_differs_from_artist_and_title {
+                song.text.as_str()
+            } else {
+                ""
+            },
+        ),
+    ];
+
+    fields
+        .into_iter()
+        .filter(|(_, value)| !value.trim().is_empty())
+        .collect()
+}
+
+// "Listeners: 69 ▲", "Listeners: 69 (52 unique) ▲" or "Listeners: 69 ▲ (peak 72)"
+pub fn get_progress_bar_suffix(
+    listener_count: i64,
+    unique_listener_count: Option<i64>,
+    last_listener_count: Option<i64>,
+    peak_listener_count: Option<i64>,
+) -> String {
+    let trend = match last_listener_count {
+        Some(last) if listener_count > last => "▲",
+        Some(last) if listener_count < last => "▼",
+        _ => "–",
+    };
+
+    let listeners = match unique_listener_count {
+        Some(unique) => format!("{listener_count} ({unique} unique)"),
+        None => listener_count.to_string(),
+    };
+
+    match peak_listener_count {
+        Some(peak) => format!("Listeners: {listeners} {trend} (peak {peak})"),
+        None => format!("Listeners: {listeners} {trend}"),
+    }
+}
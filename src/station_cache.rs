@@ -0,0 +1,79 @@
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::Mutex;
+
+use crate::model::Remote;
+
+const STATIONS_CACHE_FILE_NAME: &str = "1d7c2b0e-5f3a-4d6e-9c8b-7a2f4e6d1b3c";
+const STATIONS_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+
+static STATIONS_CACHE_FILE_PATH: Lazy<PathBuf> = Lazy::new(|| {
+    let mut pathbuf = std::env::temp_dir();
+    pathbuf.push(STATIONS_CACHE_FILE_NAME);
+    pathbuf
+});
+
+static FILE_IO_MUTEX: Mutex<()> = Mutex::const_new(());
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct StationsCache {
+    api_url: String, // The cache is invalid if fetched from another API
+    cached_at: u64,  // Unix timestamp in seconds
+    stations: Vec<Remote>,
+}
+
+/// Returns `None` if the cache file doesn't exist, is expired, or was fetched from another API url
+pub async fn try_read_stations_from_cache_file(api_url: &str) -> Option<Vec<Remote>> {
+    let _file_io_mutex_guard = FILE_IO_MUTEX.lock().await;
+
+    let cache_file_content = tokio::fs::read_to_string(STATIONS_CACHE_FILE_PATH.as_path())
+        .await
+        .ok()?;
+    let cache: StationsCache = serde_json::from_str(cache_file_content.as_str()).ok()?;
+
+    let age = now().checked_sub(cache.cached_at)?;
+    if cache.api_url != api_url || age > STATIONS_CACHE_TTL.as_secs() {
+        return None;
+    }
+
+    Some(cache.stations)
+}
+
+pub async fn write_stations_to_cache_file(api_url: &str, stations: &[Remote]) -> Result<()> {
+    let _file_io_mutex_guard = FILE_IO_MUTEX.lock().await;
+
+    let cache = StationsCache {
+        api_url: api_url.to_owned(),
+        cached_at: now(),
+        stations: stations.to_vec(),
+    };
+    let cache_file_content = serde_json::to_string_pretty(&cache)?;
+    tokio::fs::write(
+        STATIONS_CACHE_FILE_PATH.as_path(),
+        cache_file_content.as_bytes(),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Does nothing if the cache file doesn't exist
+pub async fn remove_cache_file() -> Result<()> {
+    let _file_io_mutex_guard = FILE_IO_MUTEX.lock().await;
+
+    match tokio::fs::remove_file(STATIONS_CACHE_FILE_PATH.as_path()).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
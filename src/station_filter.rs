@@ -0,0 +1,187 @@
+use crate::model::Remote;
+
+/// Filters stations by name with `--include` and `--exclude` patterns.
+///
+/// A pattern with `*` or `?` is a glob matching the whole name, otherwise it matches any part of the name.
+/// Matching is case-insensitive.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct StationFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl StationFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Self {
+        let lowercase = |patterns: &[String]| patterns.iter().map(|p| p.to_lowercase()).collect();
+        Self {
+            include: lowercase(include),
+            exclude: lowercase(exclude),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Excluding wins over including. Without include patterns, all stations are included.
+    pub fn matches(&self, station_name: &str) -> bool {
+        let station_name = station_name.to_lowercase();
+        let matches_any = |patterns: &[String]| {
+            patterns
+                .iter()
+                .any(|pattern| pattern_matches(pattern, &station_name))
+        };
+
+        (self.include.is_empty() || matches_any(&self.include)) && !matches_any(&self.exclude)
+    }
+
+    pub fn apply(&self, mut stations: Vec<Remote>) -> Vec<Remote> {
+        stations.retain(|station| self.matches(&station.name));
+        stations
+    }
+
+    /// The URL to play when no station was asked for: `default_url`, or the first of the filtered `stations`
+    /// if the default one is filtered out. `None` if no station matches.
+    pub fn default_station_url<'a>(
+        &self,
+        stations: &'a [Remote],
+        default_url: &'a str,
+    ) -> Option<&'a str> {
+        if self.is_empty() || stations.iter().any(|s| s.url == default_url) {
+            return Some(default_url);
+        }
+        stations.first().map(|s| s.url.as_str())
+    }
+}
+
+fn pattern_matches(pattern: &str, text: &str) -> bool {
+    if pattern.contains(['*', '?']) {
+        let pattern: Vec<char> = pattern.chars().collect();
+        let text: Vec<char> = text.chars().collect();
+        glob_matches(&pattern, &text)
+    } else {
+        text.contains(pattern)
+    }
+}
+
+// `*` matches any characters, `?` matches one character
+fn glob_matches(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some(('*', rest)) => (0..=text.len()).any(|i| glob_matches(rest, &text[i..])),
+        Some((&p, rest)) => match text.split_first() {
+            Some((&t, text_rest)) if p == '?' || p == t => glob_matches(rest, text_rest),
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::test_fixtures::named_remote;
+
+    fn new_filter(include: &[&str], exclude: &[&str]) -> StationFilter {
+        let to_strings =
+            |patterns: &[&str]| patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        StationFilter::new(&to_strings(include), &to_strings(exclude))
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        let filter = new_filter(&[], &[]);
+        assert!(filter.is_empty());
+        assert!(filter.matches("Code Radio"));
+    }
+
+    #[test]
+    fn substring_patterns() {
+        let filter = new_filter(&["radio"], &[]);
+        assert!(filter.matches("Code Radio HQ"));
+        assert!(!filter.matches("Low Bitrate"));
+    }
+
+    #[test]
+    fn matching_is_case_insensitive() {
+        assert!(new_filter(&["HQ"], &[]).matches("code radio hq"));
+        assert!(!new_filter(&[], &["LOW"]).matches("Low Bitrate"));
+    }
+
+    #[test]
+    fn excluding_wins_over_including() {
+        let filter = new_filter(&["radio"], &["relay"]);
+        assert!(filter.matches("Code Radio"));
+        assert!(!filter.matches("Code Radio Relay"));
+    }
+
+    #[test]
+    fn glob_patterns_match_the_whole_name() {
+        let filter = new_filter(&["code*"], &[]);
+        assert!(filter.matches("Code Radio"));
+        assert!(!filter.matches("The Code Radio"));
+
+        let filter = new_filter(&["h?"], &[]);
+        assert!(filter.matches("HQ"));
+        assert!(!filter.matches("HQ2"));
+        assert!(!filter.matches("H"));
+
+        assert!(new_filter(&["*relay*"], &[]).matches("Code Radio Relay 2"));
+    }
+
+    #[test]
+    fn apply_keeps_matching_stations_in_order() {
+        let stations = vec![
+            named_remote("Code Radio HQ", "https://radio.example.com/hq"),
+            named_remote("Code Radio Low", "https://radio.example.com/low"),
+            named_remote("Code Radio MP3", "https://radio.example.com/mp3"),
+        ];
+        let names =
+            |stations: Vec<Remote>| stations.into_iter().map(|s| s.name).collect::<Vec<_>>();
+
+        assert_eq!(
+            names(new_filter(&["radio"], &["low"]).apply(stations.clone())),
+            ["Code Radio HQ", "Code Radio MP3"]
+        );
+        assert_eq!(names(new_filter(&[], &[]).apply(stations.clone())).len(), 3);
+        assert!(new_filter(&["aac"], &[]).apply(stations).is_empty());
+    }
+
+    #[test]
+    fn default_station_url_without_filter() {
+        let stations = [named_remote("Low", "https://radio.example.com/low")];
+        assert_eq!(
+            new_filter(&[], &[]).default_station_url(&stations, "https://radio.example.com/hq"),
+            Some("https://radio.example.com/hq")
+        );
+    }
+
+    #[test]
+    fn default_station_url_keeps_the_default_if_it_matches() {
+        let stations = [
+            named_remote("Low", "https://radio.example.com/low"),
+            named_remote("HQ", "https://radio.example.com/hq"),
+        ];
+        assert_eq!(
+            new_filter(&["hq", "low"], &[])
+                .default_station_url(&stations, "https://radio.example.com/hq"),
+            Some("https://radio.example.com/hq")
+        );
+    }
+
+    #[test]
+    fn default_station_url_falls_back_to_the_first_matching_station() {
+        let filter = new_filter(&[], &["hq"]);
+        let stations = filter.apply(vec![
+            named_remote("HQ", "https://radio.example.com/hq"),
+            named_remote("Low", "https://radio.example.com/low"),
+        ]);
+        assert_eq!(
+            filter.default_station_url(&stations, "https://radio.example.com/hq"),
+            Some("https://radio.example.com/low")
+        );
+        assert_eq!(
+            filter.default_station_url(&[], "https://radio.example.com/hq"),
+            None
+        );
+    }
+}
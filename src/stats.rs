@@ -0,0 +1,229 @@
+use indicatif::HumanBytes;
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    io::Read,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{model::Remote, mp3_stream_decoder::AudioFormat, utils};
+
+// Artists beyond this are left out of the listening summary
+const MAX_ARTISTS_IN_SUMMARY: usize = 10;
+
+/// Session statistics for diagnosing flaky connections
+pub static STATS: Lazy<Mutex<Stats>> = Lazy::new(|| Mutex::new(Stats::new()));
+
+pub struct Stats {
+    start_time: Instant,
+    pub reconnect_count: u64,
+    pub bytes_streamed: u64,
+    pub audio_format: Option<AudioFormat>, // What the stream is decoded as
+    pub listening: ListeningStats,
+}
+
+/// How long each station and artist has been listened to, and how many of their songs were played.
+/// Paused and buffering time doesn't count.
+#[derive(Debug, Default)]
+pub struct ListeningStats {
+    stations: HashMap<i64, Listening>, // By station id
+    artists: HashMap<String, Listening>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Listening {
+    pub name: String,
+    pub duration: Duration,
+    pub songs: u64,
+}
+
+impl Stats {
+    fn new() -> Self {
+        Self {
+            start_time: Instant::now(),
+            reconnect_count: 0,
+            bytes_streamed: 0,
+            audio_format: None,
+            listening: ListeningStats::default(),
+        }
+    }
+
+    /// Like "Uptime: 12:34    Reconnects: 0    Streamed: 11.52 MiB    Audio: 44.1 kHz stereo"
+    pub fn summary(&self) -> String {
+        let uptime =
+            utils::humanize_seconds_to_minutes_and_seconds(self.start_time.elapsed().as_secs());
+        let mut summary = format!(
+            "Uptime: {}    Reconnects: {}    Streamed: {}",
+            uptime,
+            self.reconnect_count,
+            HumanBytes(self.bytes_streamed)
+        );
+        if let Some(audio_format) = self.audio_format {
+            summary.push_str(&format!("    Audio: {audio_format}"));
+        }
+        summary
+    }
+}
+
+impl ListeningStats {
+    pub fn add_time(&mut self, station: Option<&Remote>, artist: &str, duration: Duration) {
+        for listening in self.entries(station, artist) {
+            listening.duration += duration;
+        }
+    }
+
+    /// Call when a song starts playing
+    pub fn add_song(&mut self, station: Option<&Remote>, artist: &str) {
+        for listening in self.entries(station, artist) {
+            listening.songs += 1;
+        }
+    }
+
+    // The station's and the artist's entries. An unknown station or an empty artist has no entry.
+    fn entries(&mut self, station: Option<&Remote>, artist: &str) -> Vec<&mut Listening> {
+        let station_entry = station.map(|station| {
+            self.stations
+                .entry(station.id)
+                .or_insert_with(|| Listening {
+                    name: station.name.clone(),
+                    ..Default::default()
+                })
+        });
+        let artist_entry = (!artist.is_empty()).then(|| {
+            self.artists
+                .entry(artist.to_owned())
+                .or_insert_with(|| Listening {
+                    name: artist.to_owned(),
+                    ..Default::default()
+                })
+        });
+        station_entry.into_iter().chain(artist_entry).collect()
+    }
+
+    /// Station ids and their listening, most listened first
+    pub fn by_station(&self) -> Vec<(i64, &Listening)> {
+        let mut stations: Vec<_> = self.stations.iter().map(|(id, l)| (*id, l)).collect();
+        stations.sort_by_key(|(_, listening)| std::cmp::Reverse(listening.duration));
+        stations
+    }
+
+    /// Most listened first
+    pub fn by_artist(&self) -> Vec<&Listening> {
+        let mut artists: Vec<_> = self.artists.values().collect();
+        artists.sort_by(|a, b| b.duration.cmp(&a.duration).then(a.name.cmp(&b.name)));
+        artists
+    }
+
+    /// A table of listening time by station, then by artist, for `--print-stats-on-exit`. Like:
+    ///
+    /// ```text
+    /// Listened by station:
+    ///   1:02:14  Code Radio (17 songs)
+    /// ```
+    pub fn summary(&self) -> String {
+        let format_row = |listening: &Listening| {
+            let songs = match listening.songs {
+                1 => "1 song".to_owned(),
+                n => format!("{n} songs"),
+            };
+            format!(
+                "  {:>8}  {} ({songs})\n",
+                utils::humanize_seconds_to_minutes_and_seconds(listening.duration.as_secs()),
+                listening.name
+            )
+        };
+
+        let mut summary = String::from("Listened by station:\n");
+        for (_, listening) in self.by_station() {
+            summary.push_str(&format_row(listening));
+        }
+        summary.push_str("Listened by artist:\n");
+        for listening in self.by_artist().into_iter().take(MAX_ARTISTS_IN_SUMMARY) {
+            summary.push_str(&format_row(listening));
+        }
+        summary.trim_end().to_owned()
+    }
+
+    /// The counters in OpenMetrics text format, for the web UI's `/metrics`
+    pub fn openmetrics(&self) -> String {
+        let mut text = String::new();
+
+        text.push_str("# TYPE code_radio_station_listening_seconds counter\n");
+        for (id, listening) in self.by_station() {
+            let _ = writeln!(
+                text,
+                "code_radio_station_listening_seconds_total{{station_id=\"{id}\",station=\"{}\"}} {}",
+                escape_label_value(&listening.name),
+                listening.duration.as_secs()
+            );
+        }
+        text.push_str("# TYPE code_radio_station_songs counter\n");
+        for (id, listening) in self.by_station() {
+            let _ = writeln!(
+                text,
+                "code_radio_station_songs_total{{station_id=\"{id}\",station=\"{}\"}} {}",
+                escape_label_value(&listening.name),
+                listening.songs
+            );
+        }
+        text.push_str("# TYPE code_radio_artist_listening_seconds counter\n");
+        for listening in self.by_artist() {
+            let _ = writeln!(
+                text,
+                "code_radio_artist_listening_seconds_total{{artist=\"{}\"}} {}",
+                escape_label_value(&listening.name),
+                listening.duration.as_secs()
+            );
+        }
+        text.push_str("# TYPE code_radio_artist_songs counter\n");
+        for listening in self.by_artist() {
+            let _ = writeln!(
+                text,
+                "code_radio_artist_songs_total{{artist=\"{}\"}} {}",
+                escape_label_value(&listening.name),
+                listening.songs
+            );
+        }
+        text.push_str("# EOF\n");
+
+        text
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Wraps a reader and adds the bytes read from it to `STATS.bytes_streamed`
+#[derive(Debug)]
+pub struct CountingReader<R>
+where
+    R: Read,
+{
+    inner: R,
+}
+
+impl<R> CountingReader<R>
+where
+    R: Read,
+{
+    pub const fn new(inner: R) -> Self {
+        Self { inner }
+    }
+}
+
+impl<R> Read for CountingReader<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        STATS.lock().unwrap().bytes_streamed += n as u64;
+        Ok(n)
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    collections::VecDeque,
+    io::Read,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+};
+
+use crate::mp3_stream_decoder::BufferingMonitor;
+
+// Bitrate used if the stream doesn't tell, in kbps
+const DEFAULT_BITRATE: u32 = 128;
+
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// Reads the stream ahead in a background thread, so that playback sits some seconds behind the live edge
+/// and survives network jitter.
+///
+/// Reading blocks until the buffer is filled. After that, the buffer stays around that size, since the stream
+/// arrives at the same speed as it's played. It never grows beyond that size. If the network stalls long enough to
+/// drain the buffer, reading blocks again until it's refilled, rather than playing every chunk as it arrives.
+pub struct BufferAheadReader {
+    shared: Arc<(Mutex<SharedBuffer>, Condvar)>,
+    filled: bool, // Cleared when the buffer runs dry
+    size: usize,
+    monitor: BufferingMonitor, // Reports how full the buffer is
+}
+
+#[derive(Default)]
+struct SharedBuffer {
+    data: VecDeque<u8>,
+    finished: bool, // The inner reader reached EOF or failed
+    closed: bool,   // `BufferAheadReader` is dropped
+}
+
+impl BufferAheadReader {
+    /// `bitrate` in kbps, like the "icy-br" header of Icecast streams
+    pub fn new<R>(
+        mut inner: R,
+        seconds: u32,
+        bitrate: Option<u32>,
+        monitor: BufferingMonitor,
+    ) -> Self
+    where
+        R: Read + Send + 'static,
+    {
+        let size = (bitrate.unwrap_or(DEFAULT_BITRATE) as usize * 1000 / 8) * seconds as usize;
+        let shared = Arc::new((Mutex::new(SharedBuffer::default()), Condvar::new()));
+
+        let thread_shared = shared.clone();
+        let thread_monitor = monitor.clone();
+        thread::spawn(move || {
+            let (buffer, condvar) = &*thread_shared;
+            let mut chunk = [0; READ_CHUNK_SIZE];
+            loop {
+                let read_len = inner.read(&mut chunk).unwrap_or(0);
+
+                let mut buffer = buffer.lock().unwrap();
+                while !buffer.closed && buffer.data.len() >= size {
+                    buffer = condvar.wait(buffer).unwrap();
+                }
+                if buffer.closed {
+                    return;
+                }
+                if read_len == 0 {
+                    buffer.finished = true;
+                    condvar.notify_all();
+                    return;
+                }
+                buffer.data.extend(&chunk[..read_len]);
+                thread_monitor.set_buffer_fill(fill_of(buffer.data.len(), size));
+                condvar.notify_all();
+            }
+        });
+
+        Self {
+            shared,
+            filled: false,
+            size,
+            monitor,
+        }
+    }
+}
+
+fn fill_of(len: usize, size: usize) -> f32 {
+    (len as f32 / size.max(1) as f32).min(1.0)
+}
+
+impl Read for BufferAheadReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let (buffer, condvar) = &*self.shared;
+        let mut buffer = buffer.lock().unwrap();
+
+        let min_len = if self.filled { 1 } else { self.size.max(1) };
+        while !buffer.finished && buffer.data.len() < min_len {
+            buffer = condvar.wait(buffer).unwrap();
+        }
+
+        let read_len = buf.len().min(buffer.data.len());
+        for (byte, data) in buf.iter_mut().zip(buffer.data.drain(..read_len)) {
+            *byte = data;
+        }
+        // Ran dry, playback stalls from here
+        self.filled = !buffer.data.is_empty() || buffer.finished;
+        self.monitor
+            .set_buffer_fill(fill_of(buffer.data.len(), self.size));
+        condvar.notify_all();
+        Ok(read_len)
+    }
+}
+
+impl Drop for BufferAheadReader {
+    fn drop(&mut self) {
+        let (buffer, condvar) = &*self.shared;
+        buffer.lock().unwrap().closed = true;
+        condvar.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{self, Receiver};
+
+    // Returns each chunk sent to it as one read, and EOF once the sender is dropped
+    struct ChannelReader(Receiver<Vec<u8>>);
+
+    impl Read for ChannelReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let Ok(chunk) = self.0.recv() else {
+                return Ok(0);
+            };
+            buf[..chunk.len()].copy_from_slice(&chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    // 1 second at 8 kbps is 1000 bytes
+    fn buffer_ahead_reader() -> (BufferAheadReader, mpsc::Sender<Vec<u8>>) {
+        let (sender, receiver) = mpsc::channel();
+        let reader = BufferAheadReader::new(
+            ChannelReader(receiver),
+            1,
+            Some(8),
+            BufferingMonitor::default(),
+        );
+        (reader, sender)
+    }
+
+    #[test]
+    fn reads_once_filled() {
+        let (mut reader, sender) = buffer_ahead_reader();
+        sender.send(vec![1; 600]).unwrap();
+        sender.send(vec![2; 400]).unwrap();
+
+        let mut buf = [0; 2000];
+        assert_eq!(reader.read(&mut buf).unwrap(), 1000);
+        assert_eq!(buf[599..601], [1, 2]);
+    }
+
+    #[test]
+    fn refills_after_running_dry() {
+        let (mut reader, sender) = buffer_ahead_reader();
+        let mut buf = [0; 2000];
+        sender.send(vec![1; 1000]).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 1000);
+
+        sender.send(vec![2; 500]).unwrap();
+        sender.send(vec![3; 500]).unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 1000);
+        assert_eq!(buf[499..501], [2, 3]);
+    }
+
+    #[test]
+    fn reads_the_rest_at_the_end_of_the_stream() {
+        let (mut reader, sender) = buffer_ahead_reader();
+        sender.send(vec![1; 300]).unwrap();
+        drop(sender);
+
+        let mut buf = [0; 2000];
+        assert_eq!(reader.read(&mut buf).unwrap(), 300);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    }
+}
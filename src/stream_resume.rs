@@ -0,0 +1,361 @@
+use reqwest::{
+    blocking::{Client, Response},
+    header, StatusCode,
+};
+use std::{
+    collections::VecDeque,
+    io::{self, Read},
+    thread,
+    time::{Duration, Instant},
+};
+
+const RECONNECT_ATTEMPTS: u32 = 3;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+// The last bytes read before the connection dropped, looked for in the new connection's data
+const OVERLAP_TAIL_LEN: usize = 2048;
+
+// Icecast sends a burst of recent audio on connect, 64 KB by default. It arrives right away,
+// so the overlap is only looked for in what arrives this soon, and only up to this much.
+const OVERLAP_SEARCH_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_OVERLAP_SEARCH_LEN: usize = 256 * 1024;
+
+const READ_CHUNK_SIZE: usize = 4096;
+
+/// Reads the audio stream, and reconnects when the connection drops so that playback continues
+/// near where it left off instead of stopping.
+///
+/// Servers supporting range requests resume at the byte offset. Live servers send recent audio on connect,
+/// which was already played, so the new data is skipped up to the end of where it repeats the last data read,
+/// or entirely if no part repeats it.
+pub struct ResumingReader {
+    client: Client,
+    listen_url: String,
+    response: Response,
+    supports_ranges: bool,
+    offset: u64,           // Bytes read so far
+    tail: VecDeque<u8>,    // The last `OVERLAP_TAIL_LEN` bytes read
+    pending: VecDeque<u8>, // Read from the new connection while looking for the overlap
+}
+
+impl ResumingReader {
+    /// `response` is of the first connection to `listen_url`
+    pub fn new(client: Client, listen_url: &str, response: Response) -> Self {
+        let supports_ranges = accepts_byte_ranges(&response);
+        Self {
+            client,
+            listen_url: listen_url.to_owned(),
+            response,
+            supports_ranges,
+            offset: 0,
+            tail: VecDeque::with_capacity(OVERLAP_TAIL_LEN),
+            pending: VecDeque::new(),
+        }
+    }
+
+    // Returns false if all attempts failed
+    fn reconnect(&mut self) -> bool {
+        for attempt in 0..RECONNECT_ATTEMPTS {
+            if attempt > 0 {
+                thread::sleep(RECONNECT_DELAY);
+            }
+            if let Ok(response) = self.request() {
+                self.resume(response);
+                return true;
+            }
+        }
+        false
+    }
+
+    fn request(&self) -> reqwest::Result<Response> {
+        let mut request = self.client.get(&self.listen_url);
+        if self.supports_ranges {
+            request = request.header(header::RANGE, format!("bytes={}-", self.offset));
+        }
+        request.send()?.error_for_status()
+    }
+
+    fn resume(&mut self, mut response: Response) {
+        // A partial content response continues right where it left off, otherwise the overlap is skipped
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            let tail: Vec<u8> = self.tail.iter().copied().collect();
+            self.pending.extend(read_past_overlap(&mut response, &tail));
+        }
+        self.response = response;
+    }
+
+    fn remember(&mut self, data: &[u8]) {
+        self.offset += data.len() as u64;
+        let data = &data[data.len().saturating_sub(OVERLAP_TAIL_LEN)..];
+        let overflow = (self.tail.len() + data.len()).saturating_sub(OVERLAP_TAIL_LEN);
+        self.tail.drain(..overflow);
+        self.tail.extend(data);
+    }
+}
+
+impl Read for ResumingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read_len = if self.pending.is_empty() {
+                match self.response.read(buf) {
+                    Ok(0) | Err(_) if !buf.is_empty() => {
+                        if self.reconnect() {
+                            continue;
+                        }
+                        return Ok(0);
+                    }
+                    result => result?,
+                }
+            } else {
+                let read_len = buf.len().min(self.pending.len());
+                for (byte, data) in buf.iter_mut().zip(self.pending.drain(..read_len)) {
+                    *byte = data;
+                }
+                read_len
+            };
+
+            self.remember(&buf[..read_len]);
+            return Ok(read_len);
+        }
+    }
+}
+
+// From the "Accept-Ranges" header, which live Icecast streams don't send
+fn accepts_byte_ranges(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::ACCEPT_RANGES)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("bytes"))
+}
+
+// Read the burst of recent audio sent on connect, and return what follows the part repeating `tail`.
+// If no part repeats it, like after a long drop, the burst is skipped rather than played again,
+// since it's most likely old audio that can't be aligned.
+// The search stops at the first read that completes a match, without waiting for the rest of the burst.
+fn read_past_overlap(reader: &mut impl Read, tail: &[u8]) -> Vec<u8> {
+    // Nothing was played yet
+    if tail.is_empty() {
+        return Vec::new();
+    }
+
+    let search_started_at = Instant::now();
+    let mut fresh = Vec::new();
+    let mut chunk = [0; READ_CHUNK_SIZE];
+    let overlap_end = loop {
+        let read_len = reader.read(&mut chunk).unwrap_or(0);
+        // Only the new chunk, and the part before it where a match could start, needs searching
+        let search_from = fresh.len().saturating_sub(tail.len());
+        fresh.extend_from_slice(&chunk[..read_len]);
+
+        if let Some(overlap_end) = find_overlap_end(&fresh[search_from..], tail) {
+            break search_from + overlap_end;
+        }
+        if read_len == 0
+            || fresh.len() >= MAX_OVERLAP_SEARCH_LEN
+            || search_started_at.elapsed() >= OVERLAP_SEARCH_TIMEOUT
+        {
+            break fresh.len();
+        }
+    };
+
+    fresh.split_off(overlap_end)
+}
+
+// Where `fresh` stops repeating `tail`, if it contains `tail`.
+// The last match in `fresh` wins, in case the audio itself repeats. Matches arriving in later reads are not seen.
+fn find_overlap_end(fresh: &[u8], tail: &[u8]) -> Option<usize> {
+    if tail.is_empty() || fresh.len() < tail.len() {
+        return None;
+    }
+    fresh
+        .windows(tail.len())
+        .rposition(|window| window == tail)
+        .map(|start| start + tail.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+
+    const STREAM_LEN: usize = 100_000;
+    const DROPPED_AT: usize = 50_000;
+
+    // A known byte pattern which doesn't repeat, so that a repeat or a gap in playback shows
+    fn stream_data() -> Vec<u8> {
+        (0..STREAM_LEN as u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+            .collect()
+    }
+
+    // Returns the chunks one read at a time, then the end of the stream
+    struct ChunkedReader(VecDeque<Vec<u8>>);
+
+    impl ChunkedReader {
+        fn new(chunks: &[&[u8]]) -> Self {
+            Self(chunks.iter().map(|chunk| chunk.to_vec()).collect())
+        }
+    }
+
+    impl Read for ChunkedReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some(mut chunk) = self.0.pop_front() else {
+                return Ok(0);
+            };
+            let read_len = buf.len().min(chunk.len());
+            buf[..read_len].copy_from_slice(&chunk[..read_len]);
+            if read_len < chunk.len() {
+                self.0.push_front(chunk.split_off(read_len));
+            }
+            Ok(read_len)
+        }
+    }
+
+    // What plays after reconnecting: the data kept from the search, then the rest of the stream
+    fn play_after_reconnecting(chunks: &[&[u8]], tail: &[u8]) -> Vec<u8> {
+        let mut reader = ChunkedReader::new(chunks);
+        let mut played = read_past_overlap(&mut reader, tail);
+        reader.read_to_end(&mut played).unwrap();
+        played
+    }
+
+    #[test]
+    fn overlap_end_of_a_repeated_tail() {
+        assert_eq!(find_overlap_end(b"oldtailnew", b"tail"), Some(7));
+        assert_eq!(find_overlap_end(b"tail", b"tail"), Some(4));
+        assert_eq!(find_overlap_end(b"tailnew", b"tail"), Some(4));
+    }
+
+    #[test]
+    fn overlap_end_is_of_the_last_match() {
+        assert_eq!(find_overlap_end(b"tail-tail-new", b"tail"), Some(9));
+    }
+
+    #[test]
+    fn no_overlap_end_without_a_match() {
+        assert_eq!(find_overlap_end(b"something else", b"tail"), None);
+        assert_eq!(find_overlap_end(b"tai", b"tail"), None);
+        assert_eq!(find_overlap_end(b"", b"tail"), None);
+        assert_eq!(find_overlap_end(b"anything", b""), None);
+    }
+
+    #[test]
+    fn read_past_overlap_keeps_what_follows_the_tail() {
+        assert_eq!(
+            play_after_reconnecting(&[b"recent audio tail new", b" audio"], b"tail"),
+            b" new audio"
+        );
+    }
+
+    #[test]
+    fn read_past_overlap_finds_a_tail_split_across_reads() {
+        assert_eq!(
+            play_after_reconnecting(&[b"recent audio ta", b"il new", b" audio"], b"tail"),
+            b" new audio"
+        );
+    }
+
+    #[test]
+    fn read_past_overlap_finds_a_tail_longer_than_a_read() {
+        let tail = vec![7; READ_CHUNK_SIZE + 10];
+        let mut burst = vec![1; 100];
+        burst.extend(&tail);
+        burst.extend(b"new");
+        assert_eq!(play_after_reconnecting(&[&burst], &tail), b"new");
+    }
+
+    #[test]
+    fn read_past_overlap_skips_a_burst_without_the_tail() {
+        assert!(play_after_reconnecting(&[b"unrelated", b" audio"], b"tail").is_empty());
+    }
+
+    #[test]
+    fn read_past_overlap_keeps_everything_without_a_tail() {
+        assert_eq!(
+            play_after_reconnecting(&[b"first audio"], b""),
+            b"first audio"
+        );
+    }
+
+    // Serves one response per connection, made from the request headers, then closes the connection.
+    // Returns the stream URL, and the requests once all responses were sent.
+    fn serve(responses: Vec<fn(&str) -> Vec<u8>>) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/radio.mp3", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for respond in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                let mut reader = BufReader::new(&mut stream);
+                while reader.read_line(&mut request).unwrap() > 2 {}
+                stream.write_all(&respond(&request)).unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    fn response(status: &str, headers: &str, body: &[u8]) -> Vec<u8> {
+        let mut response =
+            format!("HTTP/1.1 {status}\r\nConnection: close\r\n{headers}\r\n").into_bytes();
+        response.extend(body);
+        response
+    }
+
+    fn play(url: &str) -> Vec<u8> {
+        let client = Client::new();
+        let first_response = client.get(url).send().unwrap();
+        let mut reader = ResumingReader::new(client, url, first_response);
+        let mut played = vec![0; STREAM_LEN];
+        reader.read_exact(&mut played).unwrap();
+        played
+    }
+
+    #[test]
+    fn resumes_at_the_offset_with_a_range_request() {
+        let (url, server) = serve(vec![
+            // Dropped before the announced length
+            |_| {
+                let headers = format!("Accept-Ranges: bytes\r\nContent-Length: {STREAM_LEN}\r\n");
+                response("200 OK", &headers, &stream_data()[..DROPPED_AT])
+            },
+            |request| {
+                let range = format!("range: bytes={DROPPED_AT}-");
+                assert!(request.to_lowercase().contains(&range), "{request}");
+                let headers = format!(
+                    "Accept-Ranges: bytes\r\nContent-Range: bytes {DROPPED_AT}-{}/{STREAM_LEN}\r\n",
+                    STREAM_LEN - 1
+                );
+                response(
+                    "206 Partial Content",
+                    &headers,
+                    &stream_data()[DROPPED_AT..],
+                )
+            },
+        ]);
+
+        assert!(play(&url) == stream_data());
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn skips_the_overlap_of_a_live_stream() {
+        let (url, server) = serve(vec![
+            |_| response("200 OK", "", &stream_data()[..DROPPED_AT]),
+            // Starts with a burst of recent audio, which was already played
+            |request| {
+                assert!(!request.to_lowercase().contains("range:"), "{request}");
+                response("200 OK", "", &stream_data()[DROPPED_AT - 10_000..])
+            },
+        ]);
+
+        assert!(play(&url) == stream_data());
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+}
@@ -1,10 +1,64 @@
 use colored::Colorize;
 use console::Term;
+use indicatif::ProgressDrawTarget;
 use once_cell::sync::Lazy;
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    io::{ErrorKind, IsTerminal, Write},
+    sync::Mutex,
+    time::Duration,
+};
+
+use crate::exit_code::ExitCode;
 
 pub static STDOUT: Lazy<Term> = Lazy::new(Term::stdout);
 
+// For `query_background_color()`
+#[cfg(unix)]
+const OSC_ANSWER_TIMEOUT_DECISECONDS: libc::cc_t = 2;
+#[cfg(unix)]
+const MAX_OSC_ANSWER_LEN: usize = 64;
+
+// Where `writeline!()` writes instead of `STDOUT`, if set. See `set_output()`.
+static OUTPUT: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+// Saved by `create_clean_up_helper()`. `None` if stdin is not a terminal.
+#[cfg(unix)]
+static ORIGINAL_TERMIOS: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+/// Redirect `writeline!()` output to the writer instead of the terminal, like a `Vec<u8>` behind a shared handle.
+/// Lines are written as is, without the "\r" workaround.
+pub fn set_output(output: Box<dyn Write + Send>) {
+    OUTPUT.lock().unwrap().replace(output);
+}
+
+/// Write a line to the output set by `set_output()`, or to the terminal. Used by `writeline!()`.
+pub fn write_line(line: &str) {
+    let result = match OUTPUT.lock().unwrap().as_mut() {
+        Some(output) => writeln!(output, "{line}"),
+        None => STDOUT.write_line(&format!("{line}\r")),
+    };
+    exit_if_broken_pipe(&result);
+}
+
+/// Write a line to stdout, even if `writeline!()` output is redirected by `set_output()`
+pub fn write_stdout_line(line: &str) {
+    exit_if_broken_pipe(&writeln!(std::io::stdout(), "{line}"));
+}
+
+// Without a reader, like when piped to `head` which has exited, the output is pointless.
+// Quit silently like other command line tools.
+fn exit_if_broken_pipe(result: &std::io::Result<()>) {
+    if matches!(result, Err(e) if is_broken_pipe(e)) {
+        restore_terminal_mode();
+        ExitCode::Success.exit();
+    }
+}
+
+fn is_broken_pipe(error: &std::io::Error) -> bool {
+    error.kind() == ErrorKind::BrokenPipe
+}
+
 pub fn enable_color_on_windows() {
     #[cfg(windows)]
     colored::control::set_virtual_terminal(true).unwrap();
@@ -26,6 +80,180 @@ pub fn read_char() -> std::io::Result<char> {
     STDOUT.read_char()
 }
 
+/// When to draw the animated progress bar, selected by `--progress`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProgressMode {
+    Never,
+    Auto, // Only if stdout and stderr are terminals, and the terminal is not "dumb"
+    Always,
+}
+
+impl ProgressMode {
+    /// Resolve `Auto` to `Never` or `Always`
+    pub fn resolve(self) -> Self {
+        match self {
+            Self::Auto => {
+                let is_terminal =
+                    std::io::stdout().is_terminal() && std::io::stderr().is_terminal();
+                let is_dumb = std::env::var("TERM").is_ok_and(|term| term == "dumb");
+                if is_terminal && !is_dumb {
+                    Self::Always
+                } else {
+                    Self::Never
+                }
+            }
+            mode => mode,
+        }
+    }
+
+    pub fn is_animated(self) -> bool {
+        self.resolve() == Self::Always
+    }
+
+    /// Where progress bars and spinners are drawn, repainted at most once per `refresh_interval`
+    pub fn draw_target(self, refresh_interval: Duration) -> ProgressDrawTarget {
+        let refresh_rate_hz = (1000 / refresh_interval.as_millis().max(1)).clamp(1, 255) as u8;
+        match self.resolve() {
+            Self::Always if std::io::stderr().is_terminal() => {
+                ProgressDrawTarget::term(Term::buffered_stderr(), refresh_rate_hz)
+            }
+            // `ProgressDrawTarget::term()` draws nothing if stderr is not a terminal
+            Self::Always => ProgressDrawTarget::term_like(Box::new(Term::stderr())),
+            _ => ProgressDrawTarget::hidden(),
+        }
+    }
+}
+
+/// Ask the terminal for its background color with the OSC 11 escape sequence.
+/// Components are between 0 and 1. `None` if stdin or stdout is not a terminal, or the terminal doesn't answer in time.
+///
+/// Call it before reading keyboard input, which would get the answer otherwise.
+#[cfg(unix)]
+pub fn query_background_color() -> Option<(f32, f32, f32)> {
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return None;
+    }
+
+    let original_termios = get_termios()?;
+    let mut termios = original_termios;
+    // Read the answer as it comes, without it being echoed
+    termios.c_lflag &= !(libc::ICANON | libc::ECHO);
+    termios.c_cc[libc::VMIN] = 0;
+    termios.c_cc[libc::VTIME] = OSC_ANSWER_TIMEOUT_DECISECONDS;
+    unsafe {
+        libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios);
+    }
+
+    let mut answer = Vec::new();
+    let mut stdout = std::io::stdout();
+    if stdout.write_all(b"\x1b]11;?\x07").is_ok() && stdout.flush().is_ok() {
+        // Terminated by BEL or ST ("\x1b\\")
+        while !(answer.ends_with(b"\x07") || answer.ends_with(b"\x1b\\"))
+            && answer.len() < MAX_OSC_ANSWER_LEN
+        {
+            let mut byte = 0u8;
+            let read = unsafe { libc::read(libc::STDIN_FILENO, (&mut byte as *mut u8).cast(), 1) };
+            if read != 1 {
+                break; // Timed out
+            }
+            answer.push(byte);
+        }
+    }
+
+    unsafe {
+        libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &original_termios);
+    }
+
+    parse_osc_11_answer(&String::from_utf8_lossy(&answer))
+}
+
+#[cfg(not(unix))]
+pub fn query_background_color() -> Option<(f32, f32, f32)> {
+    None
+}
+
+/// Copy text to the clipboard with the OSC 52 escape sequence, which most terminals support, also over SSH.
+/// False if stdout is not a terminal.
+pub fn copy_to_clipboard(text: &str) -> bool {
+    let mut stdout = std::io::stdout();
+    stdout.is_terminal()
+        && write!(stdout, "\x1b]52;c;{}\x07", base64::encode(text)).is_ok()
+        && stdout.flush().is_ok()
+}
+
+/// Run a prompt, and cancel it if it's not answered in time, for `--select-timeout`. `None` on timeout.
+///
+/// The prompt blocks reading keyboard input with no way to stop it, so on timeout its read is interrupted by a
+/// signal. It runs in its own thread rather than `spawn_blocking()`, so that the signal can't hit a thread of the
+/// blocking pool which has moved on to other work.
+#[cfg(unix)]
+pub async fn prompt_with_timeout<T>(
+    prompt: impl FnOnce() -> T + Send + 'static,
+    timeout: Duration,
+) -> Option<T>
+where
+    T: Send + 'static,
+{
+    use std::os::unix::thread::JoinHandleExt;
+
+    extern "C" fn do_nothing(_: libc::c_int) {}
+
+    let original_termios = get_termios();
+    let (answer_sender, mut answer_receiver) = tokio::sync::oneshot::channel();
+    let prompt_thread = std::thread::spawn(move || {
+        let _ = answer_sender.send(prompt());
+    });
+    if let Ok(answer) = tokio::time::timeout(timeout, &mut answer_receiver).await {
+        return answer.ok();
+    }
+
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = do_nothing as *const () as usize;
+        libc::sigaction(libc::SIGUSR1, &action, std::ptr::null_mut());
+        // `console` raises SIGINT when its read is interrupted, as if Ctrl+C was pressed
+        let sigint_handler = libc::signal(libc::SIGINT, libc::SIG_IGN);
+        // Until the prompt is waiting for a key, the signal doesn't stop it
+        while !prompt_thread.is_finished() {
+            libc::pthread_kill(prompt_thread.as_pthread_t(), libc::SIGUSR1);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        libc::signal(libc::SIGINT, sigint_handler);
+
+        // `console` leaves the terminal in raw mode when its read fails
+        if let Some(original_termios) = original_termios {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &original_termios);
+        }
+    }
+    let _ = prompt_thread.join();
+    None
+}
+
+// Like "\x1b]11;rgb:ffff/ffff/dddd\x07". Each component has 1 to 4 hex digits.
+#[cfg(unix)]
+fn parse_osc_11_answer(answer: &str) -> Option<(f32, f32, f32)> {
+    let rgb = answer.split_once("rgb:")?.1;
+    let rgb = rgb.trim_end_matches(['\x07', '\x1b', '\\']);
+    let mut components = rgb.split('/').map(|hex| {
+        let value = u16::from_str_radix(hex, 16).ok()?;
+        let max = 16u32.checked_pow(hex.len() as u32)? - 1;
+        Some(f32::from(value) / max as f32)
+    });
+    Some((
+        components.next()??,
+        components.next()??,
+        components.next()??,
+    ))
+}
+
+pub fn ring_bell() {
+    let result = match OUTPUT.lock().unwrap().as_mut() {
+        Some(output) => output.write_all(b"\x07"),
+        None => STDOUT.write_str("\x07"),
+    };
+    exit_if_broken_pipe(&result);
+}
+
 pub fn print_error(error: impl Display) {
     writeline!("{} {}", "Error:".bright_red(), error);
 }
@@ -47,12 +275,11 @@ pub fn print_error(error: impl Display) {
 /// This macro will move the cursor to the beginning of the line after writing a line, which fixes the bug.
 macro_rules! writeline {
     () => {
-        let _ = crate::terminal::STDOUT.write_line("\r");
+        crate::terminal::write_line("");
     };
     ($($arg:tt)+) => {
         for line in format!($($arg)+).split("\n") {
-            let line_r = format!("{}\r", line);
-            let _ = crate::terminal::STDOUT.write_line(&line_r);
+            crate::terminal::write_line(line);
         }
     };
 }
@@ -73,12 +300,44 @@ pub(crate) use writeline;
 /// This method will create an instance of `CleanUpHelper` struct, which implements `Drop` trait.
 /// When it drops, it will send SIGINT (Ctrl+C) signal to the program itself on Unix-like OS, which fixes the bug.
 /// Rust's Drop trait will guarantee the method to be called.
-pub const fn create_clean_up_helper() -> CleanUpHelper {
+///
+/// To exit with a specific exit code instead, call `CleanUpHelper::exit()`.
+/// It restores the terminal mode saved when the helper was created, then exits without sending SIGINT.
+pub fn create_clean_up_helper() -> CleanUpHelper {
+    #[cfg(unix)]
+    {
+        *ORIGINAL_TERMIOS.lock().unwrap() = get_termios();
+    }
     CleanUpHelper {}
 }
 
 pub struct CleanUpHelper {}
 
+impl CleanUpHelper {
+    pub fn exit(self, exit_code: ExitCode) -> ! {
+        restore_terminal_mode();
+        exit_code.exit()
+    }
+}
+
+// Restore the terminal mode saved by `create_clean_up_helper()`
+fn restore_terminal_mode() {
+    #[cfg(unix)]
+    if let Some(termios) = *ORIGINAL_TERMIOS.lock().unwrap() {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &termios);
+        }
+    }
+}
+
+// `None` if stdin is not a terminal
+#[cfg(unix)]
+fn get_termios() -> Option<libc::termios> {
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) };
+    (result == 0).then_some(termios)
+}
+
 impl Drop for CleanUpHelper {
     fn drop(&mut self) {
         #[cfg(unix)]
@@ -87,3 +346,47 @@ impl Drop for CleanUpHelper {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn broken_pipe_is_detected() {
+        assert!(is_broken_pipe(&io::Error::from(ErrorKind::BrokenPipe)));
+        assert!(is_broken_pipe(&io::Error::new(
+            ErrorKind::BrokenPipe,
+            "reader exited"
+        )));
+    }
+
+    #[test]
+    fn other_errors_are_not_broken_pipes() {
+        for kind in [
+            ErrorKind::Interrupted,
+            ErrorKind::WouldBlock,
+            ErrorKind::PermissionDenied,
+            ErrorKind::Other,
+        ] {
+            assert!(!is_broken_pipe(&io::Error::from(kind)), "{kind:?}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn epipe_is_a_broken_pipe() {
+        // EPIPE, which writing to a closed pipe fails with
+        assert!(is_broken_pipe(&io::Error::from_raw_os_error(32)));
+    }
+
+    // Like when piped to `head`, which has exited
+    #[cfg(unix)]
+    #[test]
+    fn writing_to_a_closed_reader_is_a_broken_pipe() {
+        let (mut writer, reader) = std::os::unix::net::UnixStream::pair().unwrap();
+        drop(reader);
+        let result = writeln!(writer, "Song:       Night Drive");
+        assert!(is_broken_pipe(&result.unwrap_err()));
+    }
+}
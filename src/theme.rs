@@ -0,0 +1,204 @@
+use anyhow::{anyhow, Result};
+use colored::{Color, ColoredString, Colorize};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+use crate::terminal;
+
+/// indicatif's default: filled, then to do
+pub const DEFAULT_PROGRESS_CHARS: &str = "█░";
+/// For `--ascii-bar`: filled, current, then to do
+pub const ASCII_PROGRESS_CHARS: &str = "#>-";
+
+static THEME: Lazy<Mutex<Theme>> = Lazy::new(|| Mutex::new(Theme::from_name(ThemeName::Default)));
+
+/// What a piece of colored text is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Station,
+    Song,
+    Artist,
+    Album,
+    Accent,  // Other labels, app name, etc.
+    Warning, // Notices like "New version available"
+}
+
+/// Built-in themes, selected by `--theme`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ThemeName {
+    Default,
+    Light, // For light terminal backgrounds
+    Mono,
+    HighContrast,
+}
+
+/// The `theme` section in the config file. Maps roles to color names like "bright blue".
+/// Roles not specified fall back to the selected built-in theme.
+///
+/// `progress_chars` are the progress bar characters, like "=> ": filled, current, then to do.
+/// More characters in the middle make the current position finer. Each must be one column wide.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub station: Option<String>,
+    pub song: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub accent: Option<String>,
+    pub warning: Option<String>,
+    pub progress_chars: Option<String>,
+}
+
+/// `None` means no color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    station: Option<Color>,
+    song: Option<Color>,
+    artist: Option<Color>,
+    album: Option<Color>,
+    accent: Option<Color>,
+    warning: Option<Color>,
+}
+
+impl Theme {
+    pub const fn from_name(name: ThemeName) -> Self {
+        match name {
+            ThemeName::Default => Self {
+                station: Some(Color::BrightGreen),
+                song: Some(Color::BrightGreen),
+                artist: Some(Color::BrightGreen),
+                album: Some(Color::BrightGreen),
+                accent: Some(Color::BrightGreen),
+                warning: Some(Color::BrightYellow),
+            },
+            ThemeName::Light => Self {
+                station: Some(Color::Green),
+                song: Some(Color::Green),
+                artist: Some(Color::Green),
+                album: Some(Color::Green),
+                accent: Some(Color::Green),
+                warning: Some(Color::Red),
+            },
+            ThemeName::Mono => Self {
+                station: None,
+                song: None,
+                artist: None,
+                album: None,
+                accent: None,
+                warning: None,
+            },
+            ThemeName::HighContrast => Self {
+                station: Some(Color::BrightCyan),
+                song: Some(Color::BrightWhite),
+                artist: Some(Color::BrightWhite),
+                album: Some(Color::BrightWhite),
+                accent: Some(Color::BrightCyan),
+                warning: Some(Color::BrightYellow),
+            },
+        }
+    }
+
+    /// Override roles with colors specified in the config file. Also validates `progress_chars`.
+    pub fn with_config(mut self, config: &ThemeConfig) -> Result<Self> {
+        if let Some(ref progress_chars) = config.progress_chars {
+            validate_progress_chars(progress_chars)?;
+        }
+
+        let overrides = [
+            (&mut self.station, &config.station),
+            (&mut self.song, &config.song),
+            (&mut self.artist, &config.artist),
+            (&mut self.album, &config.album),
+            (&mut self.accent, &config.accent),
+            (&mut self.warning, &config.warning),
+        ];
+
+        for (color, color_name) in overrides {
+            if let Some(color_name) = color_name {
+                *color = parse_color(color_name)?;
+            }
+        }
+
+        Ok(self)
+    }
+
+    const fn color(&self, role: Role) -> Option<Color> {
+        match role {
+            Role::Station => self.station,
+            Role::Song => self.song,
+            Role::Artist => self.artist,
+            Role::Album => self.album,
+            Role::Accent => self.accent,
+            Role::Warning => self.warning,
+        }
+    }
+}
+
+pub fn set_theme(theme: Theme) {
+    *THEME.lock().unwrap() = theme;
+}
+
+/// Color the text according to the current theme's color for the role
+pub fn paint(text: &str, role: Role) -> ColoredString {
+    match THEME.lock().unwrap().color(role) {
+        Some(color) => text.color(color),
+        None => text.normal(),
+    }
+}
+
+/// For `--theme-from-terminal`. `Light` on a light background, `Default` on a dark one,
+/// `None` if the background can't be detected.
+///
+/// Uses the `COLORFGBG` environment variable if set, like "15;0", otherwise asks the terminal.
+pub fn detect_from_terminal() -> Option<ThemeName> {
+    let is_light = match std::env::var("COLORFGBG") {
+        Ok(colorfgbg) => is_light_ansi_color(colorfgbg.rsplit(';').next()?.parse().ok()?),
+        Err(_) => {
+            let (r, g, b) = terminal::query_background_color()?;
+            // Relative luminance, ignoring gamma
+            0.2126 * r + 0.7152 * g + 0.0722 * b > 0.5
+        }
+    };
+    Some(if is_light {
+        ThemeName::Light
+    } else {
+        ThemeName::Default
+    })
+}
+
+// Of the 16 ANSI colors, white (7) and the bright colors except bright black (8) are light
+const fn is_light_ansi_color(color: u8) -> bool {
+    matches!(color, 7 | 9..=15)
+}
+
+// indicatif panics if there are less than 2, and misaligns the bar if they have different widths
+fn validate_progress_chars(progress_chars: &str) -> Result<()> {
+    if progress_chars.chars().count() < 2 {
+        return Err(anyhow!(
+            "Progress chars \"{progress_chars}\" in theme config need at least 2 characters"
+        ));
+    }
+    if let Some(c) = progress_chars
+        .chars()
+        .find(|c| console::measure_text_width(&c.to_string()) != 1)
+    {
+        return Err(anyhow!(
+            "Progress char \"{c}\" in theme config is not one column wide"
+        ));
+    }
+    Ok(())
+}
+
+// "bright green" -> `Some(Color::BrightGreen)`, "none" -> `None`
+fn parse_color(color_name: &str) -> Result<Option<Color>> {
+    if color_name.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+
+    color_name
+        .replace(['_', '-'], " ")
+        .parse::<Color>()
+        .map(Some)
+        .map_err(|_| anyhow!("Unknown color \"{color_name}\" in theme config"))
+}
@@ -1,49 +1,34 @@
 use anyhow::Result;
-use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
-use tokio::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use version_compare::Version;
 
-const LATEST_RELEASE_CACHE_FILE_NAME: &str = "e128c5f5-0a56-41d3-a121-1f2c8bb88417";
+use crate::config::CONFIG;
 
-static LATEST_RELEASE_CACHE_FILE_PATH: Lazy<PathBuf> = Lazy::new(|| {
-    let mut pathbuf = std::env::temp_dir();
-    pathbuf.push(LATEST_RELEASE_CACHE_FILE_NAME);
-    pathbuf
-});
+const LATEST_RELEASE_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
 
-static FILE_IO_MUTEX: Mutex<()> = Mutex::const_new(());
+// The latest release is cached in the config, so that GitHub is checked at most once per day.
+// If checking fails, the stale cache is used.
+pub async fn get_new_release(http_client: reqwest::Client) -> Result<Option<Release>> {
+    let cache = CONFIG.lock().unwrap().latest_release_cache.clone();
+    let is_cache_fresh = cache.as_ref().is_some_and(|cache| {
+        now().saturating_sub(cache.checked_at) < LATEST_RELEASE_CACHE_TTL.as_secs()
+    });
 
-// Use a cache file in temp dir to store latest release info and speed up the process of checking update
-pub async fn get_new_release() -> Result<Option<Release>> {
-    // Asynchronously fetch latest release info from GitHub, and save it to cache file
-    let get_new_release_from_github_task = tokio::spawn(get_new_release_from_github());
-
-    if let Some(cached_latest_release) = try_read_latest_release_from_cache_file().await {
-        if release_newer_than_current_package(&cached_latest_release) {
-            return Ok(Some(cached_latest_release));
-        }
-    }
-    get_new_release_from_github_task.await?
-}
+    let latest_release = match cache {
+        Some(cache) if is_cache_fresh => cache.latest_release,
+        cache => match get_latest_release_from_github(&http_client).await {
+            Ok(latest_release) => latest_release,
+            Err(e) => cache.map(|cache| cache.latest_release).ok_or(e)?,
+        },
+    };
 
-async fn get_new_release_from_github() -> Result<Option<Release>> {
-    let latest_release_from_github = get_latest_release_from_github().await?;
-    if release_newer_than_current_package(&latest_release_from_github) {
-        Ok(Some(latest_release_from_github))
-    } else {
-        Ok(None)
-    }
+    Ok(release_newer_than_current_package(&latest_release).then_some(latest_release))
 }
 
-async fn get_latest_release_from_github() -> Result<Release> {
-    let latest_github_response: GithubRelease = reqwest::Client::new()
+async fn get_latest_release_from_github(http_client: &reqwest::Client) -> Result<Release> {
+    let latest_github_response: GithubRelease = http_client
         .get("https://api.github.com/repos/JasonWei512/code-radio-cli/releases/latest")
-        .header(
-            "User-Agent",
-            "https://github.com/JasonWei512/code-radio-cli",
-        )
         .send()
         .await?
         .json()
@@ -54,31 +39,30 @@ async fn get_latest_release_from_github() -> Result<Release> {
         url: latest_github_response.html_url,
     };
 
-    let _ = write_latest_release_to_cache_file(&latest_release).await;
+    let mut config = CONFIG.lock().unwrap();
+    config.latest_release_cache = Some(LatestReleaseCache {
+        checked_at: now(),
+        latest_release: latest_release.clone(),
+    });
+    let _ = config.save();
+    drop(config);
 
     Ok(latest_release)
 }
 
-async fn try_read_latest_release_from_cache_file() -> Option<Release> {
-    let _file_io_mutex_guard = FILE_IO_MUTEX.lock().await;
-
-    let cache_file_content = tokio::fs::read_to_string(LATEST_RELEASE_CACHE_FILE_PATH.as_path())
-        .await
-        .ok()?;
-    serde_json::from_str(cache_file_content.as_str()).ok()
+/// Does nothing if nothing is cached
+pub fn clear_cache() -> Result<()> {
+    let mut config = CONFIG.lock().unwrap();
+    if config.latest_release_cache.take().is_some() {
+        config.save()?;
+    }
+    Ok(())
 }
 
-async fn write_latest_release_to_cache_file(release: &Release) -> Result<()> {
-    let _file_io_mutex_guard = FILE_IO_MUTEX.lock().await;
-
-    let cache_file_content = serde_json::to_string_pretty(release)?;
-    tokio::fs::write(
-        &LATEST_RELEASE_CACHE_FILE_PATH.as_path(),
-        cache_file_content.as_bytes(),
-    )
-    .await?;
-
-    Ok(())
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
 }
 
 fn release_newer_than_current_package(release: &Release) -> bool {
@@ -90,6 +74,13 @@ fn release_newer_than_current_package(release: &Release) -> bool {
     false
 }
 
+/// Stored in the config file
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatestReleaseCache {
+    checked_at: u64, // Unix timestamp in seconds
+    latest_release: Release,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Release {
     pub version: String, // Like "1.3.5"
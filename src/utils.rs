@@ -1,5 +1,195 @@
+use anyhow::{anyhow, Result};
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+use tokio::time::sleep;
+
+// 74 -> "01:14"
+// 4474 -> "1:14:34"
 pub fn humanize_seconds_to_minutes_and_seconds(seconds: u64) -> String {
-    format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+// Elapsed seconds beyond this are treated as bad data when the song's duration is unknown
+const MAX_ELAPSED_SECONDS_OF_UNKNOWN_DURATION: u64 = 24 * 60 * 60;
+
+// The API may report a negative elapsed time or one past the song's end, like on clock skew
+pub fn clamp_elapsed_seconds(elapsed_seconds: i64, total_seconds: i64) -> u64 {
+    let max_elapsed_seconds = if total_seconds > 0 {
+        total_seconds as u64
+    } else {
+        MAX_ELAPSED_SECONDS_OF_UNKNOWN_DURATION
+    };
+    (elapsed_seconds.max(0) as u64).min(max_elapsed_seconds)
+}
+
+/// Parse a human duration like "90m", "2h", "45s" or "1h30m"
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    let invalid =
+        || anyhow!("Invalid duration \"{s}\", expected like \"90m\", \"2h\" or \"1h30m\"");
+
+    let mut total_seconds: u64 = 0;
+    let mut number = String::new();
+    for c in s.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit_seconds = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let value: u64 = number.parse().map_err(|_| invalid())?;
+        total_seconds = value
+            .checked_mul(unit_seconds)
+            .and_then(|seconds| total_seconds.checked_add(seconds))
+            .ok_or_else(invalid)?;
+        number.clear();
+    }
+
+    // A trailing number without a unit, or nothing at all
+    if !number.is_empty() || total_seconds == 0 {
+        return Err(invalid());
+    }
+
+    Ok(Duration::from_secs(total_seconds))
+}
+
+/// Wrap the text into lines at most `width` columns wide, breaking at whitespace if possible.
+/// Wide characters like CJK count as 2 columns. Words longer than a line are broken anywhere.
+pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(2); // Fits any character
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_width = 0;
+
+    for word in text.split_whitespace() {
+        let word_width = console::measure_text_width(word);
+        if line_width > 0 && line_width + 1 + word_width <= width {
+            line.push(' ');
+            line.push_str(word);
+            line_width += 1 + word_width;
+            continue;
+        }
+        if line_width > 0 {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0;
+        }
+        if word_width <= width {
+            line.push_str(word);
+            line_width = word_width;
+            continue;
+        }
+        for c in word.chars() {
+            let char_width = console::measure_text_width(c.encode_utf8(&mut [0; 4]));
+            if line_width + char_width > width {
+                lines.push(std::mem::take(&mut line));
+                line_width = 0;
+            }
+            line.push(c);
+            line_width += char_width;
+        }
+    }
+
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// True if the characters of `pattern` appear in `text` in order, case-insensitively.
+/// Like "cr128" matches "Code Radio (128 kbps MP3)".
+pub fn fuzzy_matches(pattern: &str, text: &str) -> bool {
+    let mut text_chars = text.chars().flat_map(char::to_lowercase);
+    pattern
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|c| !c.is_whitespace())
+        .all(|c| text_chars.any(|t| t == c))
+}
+
+/// A template with placeholders like `{title}`, parsed once and rendered many times.
+/// Unknown placeholders are kept as is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<TemplateSegment>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplateSegment {
+    Text(String),
+    Placeholder(String),
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Self {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            // Like "{title}", but not "{{title}" or "{ title}"
+            let placeholder_end = rest.find('}').filter(|end| {
+                let key = &rest[1..*end];
+                !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            });
+
+            match placeholder_end {
+                Some(end) => {
+                    if !text.is_empty() {
+                        segments.push(TemplateSegment::Text(std::mem::take(&mut text)));
+                    }
+                    segments.push(TemplateSegment::Placeholder(rest[1..end].to_owned()));
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    text.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        text.push_str(rest);
+        if !text.is_empty() {
+            segments.push(TemplateSegment::Text(text));
+        }
+
+        Self { segments }
+    }
+
+    pub fn render(&self, values: &[(&str, String)]) -> String {
+        let mut result = String::new();
+        for segment in &self.segments {
+            match segment {
+                TemplateSegment::Text(text) => result.push_str(text),
+                TemplateSegment::Placeholder(key) => match values.iter().find(|(k, _)| k == key) {
+                    Some((_, value)) => result.push_str(value),
+                    None => {
+                        result.push('{');
+                        result.push_str(key);
+                        result.push('}');
+                    }
+                },
+            }
+        }
+        result
+    }
 }
 
 pub fn get_current_executable_name() -> String {
@@ -18,3 +208,190 @@ fn try_get_current_executable_name() -> Option<String> {
         .to_owned()
         .into()
 }
+
+/// Call `f` until it succeeds, at most `attempts` times, sleeping `delay` between attempts.
+/// `on_retry` is called with the attempt number (starting from 2) before each retry, like for showing the progress.
+/// Returns the last error if all attempts failed.
+pub async fn retry<T, F, Fut>(
+    attempts: u32,
+    delay: Duration,
+    mut on_retry: impl FnMut(u32),
+    mut f: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+
+    loop {
+        match f().await {
+            Ok(result) => return Ok(result),
+            Err(error) => {
+                if attempt >= attempts {
+                    return Err(error);
+                }
+                attempt += 1;
+                sleep(delay).await;
+                on_retry(attempt);
+            }
+        }
+    }
+}
+
+/// Exponential backoff, doubling from `initial` up to `max`.
+/// Starts over from `initial` if no delay was needed for `reset_after`, like after a period of stable playback.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    reset_after: Duration,
+    current: Duration,
+    last_delay_at: Option<Instant>,
+}
+
+impl Backoff {
+    pub const fn new(initial: Duration, max: Duration, reset_after: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            reset_after,
+            current: initial,
+            last_delay_at: None,
+        }
+    }
+
+    pub fn next_delay(&mut self) -> Duration {
+        if self
+            .last_delay_at
+            .is_some_and(|last_delay_at| last_delay_at.elapsed() > self.reset_after)
+        {
+            self.current = self.initial;
+        }
+        self.last_delay_at = Some(Instant::now());
+
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn humanize_seconds_under_an_hour() {
+        assert_eq!(humanize_seconds_to_minutes_and_seconds(0), "00:00");
+        assert_eq!(humanize_seconds_to_minutes_and_seconds(59), "00:59");
+        assert_eq!(humanize_seconds_to_minutes_and_seconds(60), "01:00");
+        assert_eq!(humanize_seconds_to_minutes_and_seconds(3599), "59:59");
+    }
+
+    #[test]
+    fn humanize_seconds_of_an_hour_or_more() {
+        assert_eq!(humanize_seconds_to_minutes_and_seconds(3600), "1:00:00");
+        assert_eq!(humanize_seconds_to_minutes_and_seconds(4474), "1:14:34");
+        assert_eq!(humanize_seconds_to_minutes_and_seconds(36000), "10:00:00");
+    }
+
+    #[test]
+    fn clamp_elapsed_seconds_within_duration() {
+        assert_eq!(clamp_elapsed_seconds(0, 180), 0);
+        assert_eq!(clamp_elapsed_seconds(42, 180), 42);
+        assert_eq!(clamp_elapsed_seconds(180, 180), 180);
+    }
+
+    #[test]
+    fn clamp_elapsed_seconds_past_duration() {
+        assert_eq!(clamp_elapsed_seconds(200, 180), 180);
+    }
+
+    #[test]
+    fn clamp_negative_elapsed_seconds_of_clock_skew() {
+        assert_eq!(clamp_elapsed_seconds(-5, 180), 0);
+        assert_eq!(clamp_elapsed_seconds(i64::MIN, 0), 0);
+    }
+
+    #[test]
+    fn clamp_elapsed_seconds_of_unknown_duration() {
+        assert_eq!(clamp_elapsed_seconds(600, 0), 600);
+        assert_eq!(clamp_elapsed_seconds(600, -1), 600);
+        assert_eq!(
+            clamp_elapsed_seconds(i64::MAX, 0),
+            MAX_ELAPSED_SECONDS_OF_UNKNOWN_DURATION
+        );
+    }
+
+    #[test]
+    fn parse_valid_durations() {
+        assert_eq!(parse_duration("45s").unwrap(), Duration::from_secs(45));
+        assert_eq!(parse_duration("90m").unwrap(), Duration::from_secs(90 * 60));
+        assert_eq!(
+            parse_duration("2h").unwrap(),
+            Duration::from_secs(2 * 60 * 60)
+        );
+        assert_eq!(
+            parse_duration("1h30m").unwrap(),
+            Duration::from_secs(90 * 60)
+        );
+    }
+
+    #[test]
+    fn parse_invalid_durations() {
+        for s in ["", "90", "m", "1x", "-5m", "1.5h", "1h 30m"] {
+            assert!(parse_duration(s).is_err(), "{s:?} should be invalid");
+        }
+    }
+
+    #[test]
+    fn parse_durations_with_days_uppercase_units_and_whitespace() {
+        assert_eq!(
+            parse_duration("1d").unwrap(),
+            Duration::from_secs(24 * 60 * 60)
+        );
+        assert_eq!(
+            parse_duration("2H").unwrap(),
+            Duration::from_secs(2 * 60 * 60)
+        );
+        assert_eq!(
+            parse_duration(" 90m ").unwrap(),
+            Duration::from_secs(90 * 60)
+        );
+        assert_eq!(parse_duration("1h0m5s").unwrap(), Duration::from_secs(3605));
+    }
+
+    #[test]
+    fn parse_zero_and_overflowing_durations_fails() {
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("99999999999999999999s").is_err());
+        assert!(parse_duration(&format!("{}d", u64::MAX / 2)).is_err());
+    }
+
+    #[test]
+    fn wrap_text_at_whitespace() {
+        assert_eq!(wrap_text("Night Drive", 20), ["Night Drive"]);
+        assert_eq!(
+            wrap_text("Music for your coding sessions", 12),
+            ["Music for", "your coding", "sessions"]
+        );
+        assert_eq!(wrap_text("", 10), [""]);
+    }
+
+    #[test]
+    fn wrap_text_breaks_long_words() {
+        assert_eq!(wrap_text("abcdefghij", 4), ["abcd", "efgh", "ij"]);
+        assert_eq!(wrap_text("a abcdefgh", 4), ["a", "abcd", "efgh"]);
+    }
+
+    #[test]
+    fn wrap_text_counts_wide_characters_as_two_columns() {
+        assert_eq!(wrap_text("日本語の歌", 4), ["日本", "語の", "歌"]);
+        assert_eq!(wrap_text("日本 語", 5), ["日本", "語"]);
+    }
+
+    #[test]
+    fn wrap_text_with_zero_width_is_two_columns_wide() {
+        assert_eq!(wrap_text("ab 日本", 0), ["ab", "日", "本"]);
+    }
+}
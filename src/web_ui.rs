@@ -0,0 +1,371 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::Instant,
+};
+
+use crate::{
+    art_cache, model::Song, set_player_paused, set_player_volume, stats, switch_station,
+    CURRENT_STATION, LAST_MESSAGE, PLAYER, STATIONS,
+};
+
+const INDEX_HTML: &str = include_str!("web_ui.html");
+
+// Requests are small. Anything longer is rejected.
+const MAX_REQUEST_LEN: usize = 8 * 1024;
+
+// For the whole request, so that a client sending it slowly or not at all doesn't keep the connection open
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The JSON of `GET /api/status`
+#[derive(Debug, Clone, Serialize)]
+struct Status {
+    song: Option<Song>,
+    elapsed: i64,
+    duration: i64,
+    listeners: i64,
+    volume: Option<u8>, // `None` if playing without audio
+    paused: bool,
+    station_id: Option<i64>,
+    stations: Vec<StationStatus>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct StationStatus {
+    id: i64,
+    description: String,
+}
+
+/// Bind now, so that a port in use is reported on startup instead of in background
+pub async fn bind(port: u16) -> Result<TcpListener> {
+    TcpListener::bind(("0.0.0.0", port))
+        .await
+        .with_context(|| format!("Cannot listen on port {port}"))
+}
+
+/// Serve the web UI and its JSON API:
+///
+/// - `GET /`: The web UI
+/// - `GET /api/status`: The current song, volume and stations
+/// - `GET /api/art`: The current song's album art, from `art_cache`
+/// - `POST /api/volume?value=<0-9>`: Set the volume
+/// - `POST /api/pause`: Pause or resume
+/// - `POST /api/station?id=<ID>`: Switch to the station
+///
+/// POST requests from other sites are rejected, so that a web page can't control playback from the
+/// listener's browser. Their Host must be the address listened on, and their Origin if sent must match it.
+pub async fn serve(listener: TcpListener, http_client: reqwest::Client) {
+    loop {
+        if let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(handle_connection(stream, http_client.clone()));
+        }
+    }
+}
+
+// One request per connection
+async fn handle_connection(mut stream: TcpStream, http_client: reqwest::Client) {
+    let response = match (read_request(&mut stream).await, stream.local_addr()) {
+        (Some(request), Ok(local_addr)) => {
+            if request.line.starts_with("POST ") && !is_same_origin(&request, local_addr) {
+                Response::text(403, "Forbidden")
+            } else {
+                handle_request(&request.line, &http_client).await
+            }
+        }
+        _ => Response::text(400, "Bad Request"),
+    };
+    let _ = stream.write_all(&response.to_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+// The body is not used
+#[derive(Debug, Default)]
+struct Request {
+    line: String, // Like "GET /api/status HTTP/1.1"
+    host: Option<String>,
+    origin: Option<String>,
+}
+
+async fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = tokio::time::timeout_at(deadline, stream.read(&mut buf))
+            .await
+            .ok()?
+            .ok()?;
+        if n == 0 || request.len() + n > MAX_REQUEST_LEN {
+            return None;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request = String::from_utf8(request).ok()?;
+    let mut lines = request.lines();
+    let mut parsed = Request {
+        line: lines.next()?.to_owned(),
+        ..Request::default()
+    };
+    for (name, value) in lines.filter_map(|line| line.split_once(':')) {
+        let value = Some(value.trim().to_owned());
+        if name.eq_ignore_ascii_case("host") {
+            parsed.host = value;
+        } else if name.eq_ignore_ascii_case("origin") {
+            parsed.origin = value;
+        }
+    }
+    Some(parsed)
+}
+
+// The Host is the address listened on, like "192.168.1.10:8080" or "localhost:8080",
+// and the Origin if any is of that host. Other hosts may be a DNS rebinding attack.
+fn is_same_origin(request: &Request, local_addr: SocketAddr) -> bool {
+    let Some(ref host) = request.host else {
+        return false;
+    };
+    let ip = match local_addr.ip() {
+        IpAddr::V6(ip) => format!("[{ip}]"),
+        ip => ip.to_string(),
+    };
+    let port = local_addr.port();
+    let is_local_host = [ip.as_str(), "localhost"].iter().any(|name| {
+        host.eq_ignore_ascii_case(&format!("{name}:{port}"))
+            || (port == 80 && host.eq_ignore_ascii_case(name))
+    });
+
+    is_local_host
+        && request.origin.as_ref().map_or(true, |origin| {
+            origin.eq_ignore_ascii_case(&format!("http://{host}"))
+        })
+}
+
+async fn handle_request(request_line: &str, http_client: &reqwest::Client) -> Response {
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Response::text(400, "Bad Request");
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query_value = |key: &str| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v)
+    };
+
+    match (method, path) {
+        ("GET", "/") => Response {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            body: INDEX_HTML.as_bytes().to_vec(),
+        },
+        ("GET", "/api/status") => Response::json(&get_status()),
+        ("GET", "/metrics") => Response {
+            status: 200,
+            content_type: "application/openmetrics-text; version=1.0.0; charset=utf-8",
+            body: stats::STATS
+                .lock()
+                .unwrap()
+                .listening
+                .openmetrics()
+                .into_bytes(),
+        },
+        ("GET", "/api/art") => {
+            let art_url = LAST_MESSAGE
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|m| m.now_playing.song.art.clone())
+                .filter(|url| !url.is_empty());
+            let Some(art_url) = art_url else {
+                return Response::text(404, "No album art");
+            };
+            match art_cache::get(http_client, &art_url).await {
+                Ok(art) => Response {
+                    status: 200,
+                    content_type: art_cache::content_type(&art),
+                    body: art,
+                },
+                Err(_) => Response::text(503, "Cannot download album art"),
+            }
+        }
+        ("POST", "/api/volume") => {
+            let Some(volume) = query_value("value")
+                .and_then(|v| v.parse::<u8>().ok())
+                .filter(|v| *v <= 9)
+            else {
+                return Response::text(400, "Expected volume between 0 and 9");
+            };
+            match PLAYER.lock().unwrap().as_mut() {
+                Some(player) => {
+                    set_player_volume(player, volume);
+                    Response::json(&get_status())
+                }
+                None => Response::text(503, "Playing without audio"),
+            }
+        }
+        ("POST", "/api/pause") => match PLAYER.lock().unwrap().as_mut() {
+            Some(player) => {
+                set_player_paused(player, !player.is_paused());
+                Response::json(&get_status())
+            }
+            None => Response::text(503, "Playing without audio"),
+        },
+        ("POST", "/api/station") => {
+            let station_id = query_value("id").and_then(|id| id.parse::<i64>().ok());
+            let station = STATIONS
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|s| Some(s.id) == station_id)
+                .cloned();
+            match station {
+                Some(station) => {
+                    switch_station(&station);
+                    Response::json(&get_status())
+                }
+                None => Response::text(404, "Station not found"),
+            }
+        }
+        _ => Response::text(404, "Not Found"),
+    }
+}
+
+fn get_status() -> Status {
+    let last_message = LAST_MESSAGE.lock().unwrap().clone();
+    let (volume, paused) = PLAYER
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map_or((None, false), |player| {
+            (Some(player.volume()), player.is_paused())
+        });
+    let station_id = CURRENT_STATION.lock().unwrap().as_ref().map(|s| s.id);
+    let stations = STATIONS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|s| StationStatus {
+            id: s.id,
+            description: s.description(),
+        })
+        .collect();
+
+    Status {
+        song: last_message.as_ref().map(|m| m.now_playing.song.clone()),
+        elapsed: last_message.as_ref().map_or(0, |m| m.now_playing.elapsed),
+        duration: last_message.as_ref().map_or(0, |m| m.now_playing.duration),
+        listeners: last_message.as_ref().map_or(0, |m| m.listeners.current),
+        volume,
+        paused,
+        station_id,
+        stations,
+    }
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn text(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn json(value: &impl Serialize) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/json",
+            body: serde_json::to_vec(value).unwrap_or_default(),
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            404 => "Not Found",
+            _ => "Service Unavailable",
+        };
+        let mut bytes = format!(
+            "HTTP/1.1 {} {reason}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+            self.status,
+            self.content_type,
+            self.body.len(),
+        )
+        .into_bytes();
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(host: Option<&str>, origin: Option<&str>) -> Request {
+        Request {
+            line: "POST /api/pause HTTP/1.1".to_owned(),
+            host: host.map(str::to_owned),
+            origin: origin.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn same_origin_accepts_the_listening_address() {
+        let local_addr: SocketAddr = "192.168.1.10:8080".parse().unwrap();
+        for (host, origin) in [
+            (Some("192.168.1.10:8080"), None),
+            (Some("192.168.1.10:8080"), Some("http://192.168.1.10:8080")),
+            (Some("localhost:8080"), Some("http://localhost:8080")),
+            (Some("LOCALHOST:8080"), None),
+        ] {
+            assert!(
+                is_same_origin(&request(host, origin), local_addr),
+                "{host:?} {origin:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn same_origin_rejects_other_hosts_and_origins() {
+        let local_addr: SocketAddr = "192.168.1.10:8080".parse().unwrap();
+        for (host, origin) in [
+            (None, None),
+            (Some("evil.example.com:8080"), None),
+            (Some("192.168.1.10:9090"), None),
+            (Some("192.168.1.10"), None),
+            (Some("192.168.1.10:8080"), Some("http://evil.example.com")),
+            (Some("192.168.1.10:8080"), Some("https://192.168.1.10:8080")),
+        ] {
+            assert!(
+                !is_same_origin(&request(host, origin), local_addr),
+                "{host:?} {origin:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn same_origin_without_port_on_port_80() {
+        let local_addr: SocketAddr = "[::1]:80".parse().unwrap();
+        assert!(is_same_origin(&request(Some("[::1]"), None), local_addr));
+        assert!(is_same_origin(
+            &request(Some("localhost"), Some("http://localhost")),
+            local_addr
+        ));
+    }
+}
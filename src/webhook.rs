@@ -0,0 +1,113 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::sleep;
+
+use crate::{
+    http_client::parse_header,
+    model::Song,
+    print_above_progress_bar,
+    song_events::{NowPlayingSink, SongChange},
+    terminal,
+};
+
+const MAX_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    client: reqwest::Client,
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+/// The JSON body POSTed to the webhook URL on each song change
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookPayload {
+    pub song: Song,
+    pub station: Option<String>,
+    pub listeners: i64,
+    pub timestamp: u64, // When the song started, Unix timestamp in seconds
+}
+
+impl Webhook {
+    /// `headers` are like `["Authorization: Bearer <TOKEN>"]`
+    pub fn try_new(http_client: reqwest::Client, url: &str, headers: &[String]) -> Result<Self> {
+        let headers = headers
+            .iter()
+            .map(|header| parse_header(header))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            client: http_client,
+            url: url.to_owned(),
+            headers,
+        })
+    }
+
+    /// POST the payload to the webhook URL. Failed requests are retried with backoff.
+    pub async fn send(&self, payload: &WebhookPayload) -> Result<()> {
+        let mut attempt = 1;
+
+        loop {
+            match self.try_send(payload).await {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    if attempt == MAX_ATTEMPTS {
+                        return Err(
+                            e.context(format!("Webhook failed after {MAX_ATTEMPTS} attempts"))
+                        );
+                    }
+                    sleep(Duration::from_secs(2_u64.pow(attempt - 1))).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    async fn try_send(&self, payload: &WebhookPayload) -> Result<()> {
+        let mut request = self.client.post(&self.url).json(payload);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+impl NowPlayingSink for Webhook {
+    // Sent in background so that a slow or unreachable webhook never blocks playback
+    fn on_song_change(&mut self, song_change: &SongChange) {
+        let webhook = self.clone();
+        let payload = WebhookPayload::new(
+            song_change.song.clone(),
+            song_change.station_name.clone(),
+            song_change.listeners,
+            song_change.started_at,
+        );
+        tokio::spawn(async move {
+            if let Err(e) = webhook.send(&payload).await {
+                print_above_progress_bar(|| terminal::print_error(format!("{e:#}")));
+            }
+        });
+    }
+}
+
+impl WebhookPayload {
+    pub fn new(
+        song: Song,
+        station: Option<String>,
+        listeners: i64,
+        started_at: SystemTime,
+    ) -> Self {
+        let timestamp = started_at
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+
+        Self {
+            song,
+            station,
+            listeners,
+            timestamp,
+        }
+    }
+}
@@ -0,0 +1,180 @@
+// Runs the program against a mock AzuraCast server, which serves the REST API and pushes songs over the websocket API
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::Value;
+use std::{
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::mpsc::{self, Receiver},
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tokio_tungstenite::tungstenite::Message;
+
+const MESSAGE_FIXTURE: &str = include_str!("fixtures/message.json");
+
+// How long to wait for the expected output before failing
+const OUTPUT_TIMEOUT: Duration = Duration::from_secs(20);
+
+// Between the two websocket messages, so that they are shown one after another like real song changes
+const SONG_CHANGE_DELAY: Duration = Duration::from_millis(500);
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn plays_songs_pushed_by_the_server() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let api_url = format!(
+        "http://{}/api/live/nowplaying/coderadio",
+        listener.local_addr().unwrap()
+    );
+    tokio::spawn(serve(listener));
+
+    let temp_dir = create_temp_dir();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_code-radio"))
+        .args(["--no-audio", "--api-url", &api_url])
+        .env("XDG_CONFIG_HOME", &temp_dir)
+        .env("TMPDIR", &temp_dir)
+        .env("NO_COLOR", "1")
+        // The update check on GitHub fails right away instead of depending on the network
+        .env("HTTPS_PROXY", "http://127.0.0.1:9")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let lines = read_lines(child.stdout.take().unwrap());
+
+    let output =
+        tokio::task::spawn_blocking(move || wait_for_line(&lines, "Song:       Second Song"))
+            .await
+            .unwrap();
+    let _ = child.kill();
+    let _ = child.wait();
+    let _ = std::fs::remove_dir_all(&temp_dir);
+
+    let position = |expected: &str| {
+        output
+            .iter()
+            .position(|line| line == expected)
+            .unwrap_or_else(|| panic!("{expected:?} not found in output: {output:#?}"))
+    };
+    // The station is resolved from the listen url in the REST API message
+    let station = position("Station:    HQ");
+    let first_song = position("Song:       Night Drive");
+    let second_song = position("Song:       Second Song");
+    assert!(
+        station < first_song && first_song < second_song,
+        "{output:#?}"
+    );
+    assert!(
+        output.contains(&"Artist:     Lofi Artist".to_owned()),
+        "{output:#?}"
+    );
+}
+
+// The REST API and the websocket API are at the same path, like on AzuraCast
+async fn serve(listener: TcpListener) {
+    loop {
+        let (stream, _) = listener.accept().await.unwrap();
+        tokio::spawn(handle_connection(stream));
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream) {
+    if is_websocket_upgrade(&stream).await {
+        let mut websocket_stream = tokio_tungstenite::accept_async(stream).await.unwrap();
+        for message in [first_message(), second_message()] {
+            if websocket_stream
+                .send(Message::Text(message.to_string()))
+                .await
+                .is_err()
+            {
+                return;
+            }
+            tokio::time::sleep(SONG_CHANGE_DELAY).await;
+        }
+        // Keep the connection open, like a server waiting for the next song
+        while let Some(Ok(_)) = websocket_stream.next().await {}
+    } else {
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            match stream.read(&mut buf).await {
+                Ok(0) | Err(_) => return,
+                Ok(n) => request.extend_from_slice(&buf[..n]),
+            }
+        }
+        let body = first_message().to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+        let _ = stream.shutdown().await;
+    }
+}
+
+// Peeked, so that the websocket handshake can still read the request
+async fn is_websocket_upgrade(stream: &TcpStream) -> bool {
+    let mut buf = [0; 4096];
+    loop {
+        let Ok(n) = stream.peek(&mut buf).await else {
+            return false;
+        };
+        let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+        if request.contains("\r\n\r\n") || n == 0 || n == buf.len() {
+            return request.contains("upgrade: websocket");
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+fn first_message() -> Value {
+    serde_json::from_str(MESSAGE_FIXTURE).unwrap()
+}
+
+fn second_message() -> Value {
+    let mut message = first_message();
+    message["now_playing"]["sh_id"] = 999_999.into();
+    message["now_playing"]["song"]["id"] = "second".into();
+    message["now_playing"]["song"]["title"] = "Second Song".into();
+    message
+}
+
+fn create_temp_dir() -> PathBuf {
+    let temp_dir =
+        std::env::temp_dir().join(format!("code-radio-mock-server-{}", std::process::id()));
+    std::fs::create_dir_all(&temp_dir).unwrap();
+    temp_dir
+}
+
+// Read on a thread, so that waiting for a line can time out
+fn read_lines(stdout: impl std::io::Read + Send + 'static) -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            // Lines end with "\r" on terminals
+            if sender.send(line.trim_end_matches('\r').to_owned()).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+// All lines up to `expected`, or up to the timeout
+fn wait_for_line(lines: &Receiver<String>, expected: &str) -> Vec<String> {
+    let deadline = Instant::now() + OUTPUT_TIMEOUT;
+    let mut output = Vec::new();
+    while let Ok(line) = lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+        let found = line == expected;
+        output.push(line);
+        if found {
+            break;
+        }
+    }
+    output
+}